@@ -0,0 +1,258 @@
+// --- std ---
+use std::{convert::TryFrom, fmt, iter, str::FromStr};
+// --- custom ---
+use crate::{
+	builder::{Builder, Options},
+	error::{Error, Stage},
+	message::{Event, Output},
+};
+
+/// The day of a nightly, `nightly-<date>`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+	/// Since 1970-01-01
+	days: i64,
+}
+
+impl Date {
+	/// `None` if it is not a valid date of the years 1 to 9999
+	pub fn new(year: i64, month: u32, day: u32) -> Option<Self> {
+		// the days of a larger year would overflow
+		if !(1..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+			return None;
+		}
+
+		// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+		let year_ = if month <= 2 { year - 1 } else { year };
+		let era = year_.div_euclid(400);
+		let year_of_era = year_.rem_euclid(400);
+		let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) as i64 + 2) / 5 + day as i64 - 1;
+		let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+		let date = Self {
+			days: era * 146_097 + day_of_era - 719_468,
+		};
+
+		// e.g. 2019-02-30
+		if date.civil() == (year, month, day) {
+			Some(date)
+		} else {
+			None
+		}
+	}
+
+	/// The days between them, negative if `other` is later
+	pub fn days_since(self, other: Self) -> i64 {
+		self.days - other.days
+	}
+
+	pub fn add_days(self, days: i64) -> Self {
+		Self { days: self.days + days }
+	}
+
+	/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+	fn civil(self) -> (i64, u32, u32) {
+		let days = self.days + 719_468;
+		let era = days.div_euclid(146_097);
+		let day_of_era = days.rem_euclid(146_097);
+		let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+		let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+		let month_ = (5 * day_of_year + 2) / 153;
+		let day = (day_of_year - (153 * month_ + 2) / 5 + 1) as u32;
+		let month = if month_ < 10 { month_ + 3 } else { month_ - 9 } as u32;
+		let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+		(year, month, day)
+	}
+}
+
+/// `2020-10-06` or `nightly-2020-10-06`
+impl FromStr for Date {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let error = || format!("expect a date e.g. `2020-10-06`, found `{}`", s);
+		let date = s.trim_start_matches("nightly-");
+		let mut parts = date.splitn(3, '-');
+		let mut part = || parts.next().and_then(|part| part.parse().ok());
+		let (year, month, day) = (
+			part().ok_or_else(error)?,
+			part().ok_or_else(error)?,
+			part().ok_or_else(error)?,
+		);
+
+		let (month, day) = (
+			u32::try_from(month).map_err(|_| error())?,
+			u32::try_from(day).map_err(|_| error())?,
+		);
+
+		Self::new(year, month, day).ok_or_else(error)
+	}
+}
+
+impl fmt::Display for Date {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		let (year, month, day) = self.civil();
+		write!(f, "{:04}-{:02}-{:02}", year, month, day)
+	}
+}
+
+/// The result of building with a nightly
+#[derive(Debug)]
+enum Verdict {
+	Good,
+	/// With the error of the build
+	Bad(Error),
+	/// The nightly (or its targets/components) is not available, e.g. it was not published that day
+	Skip(Error),
+}
+
+/// Binary search the first nightly in `(good, bad]` which fails to build the project
+///
+/// Each nightly is installed by `Tool::new` and built like `build` (with the wasm if `options.wasm` is set),
+/// the dates which can not be installed are skipped
+pub fn bisect(options: &Options, good: Date, bad: Date) -> Result<(), Error> {
+	if good >= bad {
+		return Err(Error::Bisect(format!(
+			"the good date {} must be before the bad date {}",
+			good, bad
+		)));
+	}
+
+	let output = &options.output;
+	// the nightlies must be installed to be tested, the CI mode is told so since it never installs implicitly
+	output.progress("bisect-toolchain installs each nightly it tests, `--ci` doesn't stop it");
+	// make sure the bounds are right first, the error of `bad` is the one reported if nothing later fails
+	let bad_error = match try_nightly(options, bad)? {
+		Verdict::Bad(e) => {
+			report(output, bad, Some(&e));
+			e
+		}
+		Verdict::Good => return Err(Error::Bisect(format!("nightly-{} builds, it is not a bad date", bad))),
+		Verdict::Skip(e) => return Err(e),
+	};
+	match try_nightly(options, good)? {
+		Verdict::Good => report(output, good, None),
+		Verdict::Bad(e) => {
+			return Err(Error::Bisect(format!(
+				"nightly-{} fails to build, it is not a good date: {}",
+				good, e
+			)))
+		}
+		Verdict::Skip(e) => return Err(e),
+	}
+
+	let (mut good, mut bad, mut bad_error) = (good, bad, bad_error);
+	let mut skipped = vec![];
+	while let Some(date) = next(good, bad, &skipped) {
+		match try_nightly(options, date)? {
+			Verdict::Good => {
+				report(output, date, None);
+				good = date;
+			}
+			Verdict::Bad(e) => {
+				report(output, date, Some(&e));
+				bad = date;
+				bad_error = e;
+			}
+			Verdict::Skip(e) => {
+				output.missing(&format!("nightly-{}", date), &format!("skipped, {}", e));
+				skipped.push(date);
+			}
+		}
+	}
+
+	let excerpt = bad_error.to_string();
+	output.emit(Event::Bisect {
+		good: &format!("nightly-{}", good),
+		bad: &format!("nightly-{}", bad),
+		skipped: skipped.len(),
+		error: &excerpt,
+	});
+	output.found("last good nightly", &format!("nightly-{}", good));
+	output.missing("first bad nightly", &format!("nightly-{}\n{}", bad, excerpt));
+	if bad.days_since(good) > 1 {
+		output.missing(
+			"skipped",
+			"the nightlies between them are not available, any of them might be the first bad one",
+		);
+	}
+
+	Ok(())
+}
+
+/// The middle of the untested dates between them, `None` if there is nothing left to test
+fn next(good: Date, bad: Date, skipped: &[Date]) -> Option<Date> {
+	let days = bad.days_since(good);
+	let middle = days / 2;
+
+	// step outward from the middle past the skipped dates
+	(0..days - middle)
+		.flat_map(|step| iter::once(middle + step).chain(iter::once(middle - step)))
+		.filter(|offset| 0 < *offset && *offset < days)
+		.map(|offset| good.add_days(offset))
+		.find(|date| !skipped.contains(date))
+}
+
+fn try_nightly(options: &Options, date: Date) -> Result<Verdict, Error> {
+	let mut options = options.clone();
+	options.toolchain = format!("nightly-{}", date);
+	// the wasm is built with the nightly under test as well, not with a fixed `wasm-toolchain`
+	options.wasm_toolchain = None;
+	// the nightlies must be installed to be tested, even in the CI mode, see `bisect`
+	options.install = true;
+	// the rustc errors of the first bad nightly are reported
+	options.excerpt = true;
+	options.output.progress(&format!("trying nightly-{}", date));
+
+	let builder = match Builder::new(options).and_then(|builder| builder.check().map(|_| builder)) {
+		Ok(builder) => builder,
+		Err(e) if e.stage() == Some(Stage::Toolchain) => return Ok(Verdict::Skip(e)),
+		Err(e) => return Err(e),
+	};
+	match builder.build() {
+		Ok(_) => Ok(Verdict::Good),
+		Err(e) if [Some(Stage::Wasm), Some(Stage::Build)].contains(&e.stage()) => Ok(Verdict::Bad(e)),
+		Err(e) => Err(e),
+	}
+}
+
+/// `[✓] nightly-<date>: builds` or `[✗] nightly-<date>: <stage> failed`
+fn report(output: &Output, date: Date, error: Option<&Error>) {
+	let name = format!("nightly-{}", date);
+	match error.map(Error::stage) {
+		None => output.found(&name, "builds"),
+		Some(Some(stage)) => output.missing(&name, &format!("{} failed", stage)),
+		Some(None) => output.missing(&name, "failed"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn nightly_dates() {
+		let date = "nightly-2020-02-28".parse::<Date>().unwrap();
+		assert_eq!(date.add_days(1).to_string(), "2020-02-29");
+		assert_eq!(date.add_days(2).to_string(), "2020-03-01");
+		assert_eq!("2021-01-01".parse::<Date>().unwrap().days_since(date), 308);
+		assert!("2019-02-29".parse::<Date>().is_err());
+		assert!("2019-13-01".parse::<Date>().is_err());
+		assert!("nightly".parse::<Date>().is_err());
+		assert!("99999999999999999-01-01".parse::<Date>().is_err());
+
+		let (good, bad) = ("2020-10-01".parse().unwrap(), "2020-10-09".parse().unwrap());
+		assert_eq!(next(good, bad, &[]).unwrap().to_string(), "2020-10-05");
+		// the nearest untested date to the middle
+		let skipped = ["2020-10-05".parse().unwrap()];
+		assert_eq!(next(good, bad, &skipped).unwrap().to_string(), "2020-10-06");
+		let skipped = ["2020-10-05".parse().unwrap(), "2020-10-06".parse().unwrap()];
+		assert_eq!(next(good, bad, &skipped).unwrap().to_string(), "2020-10-04");
+		let skipped = (2..8).map(|days| good.add_days(days)).collect::<Vec<_>>();
+		assert_eq!(next(good, bad, &skipped).unwrap().to_string(), "2020-10-02");
+		assert_eq!(next(date, "2021-01-01".parse().unwrap(), &[]).unwrap(), date.add_days(154));
+		let skipped = (1..8).map(|days| good.add_days(days)).collect::<Vec<_>>();
+		assert_eq!(next(good, bad, &skipped), None);
+		assert_eq!(next(good, good.add_days(1), &[]), None);
+	}
+}
@@ -0,0 +1,773 @@
+// --- std ---
+use std::{
+	env, fmt, fs,
+	io::{self, Read, Write},
+	path::{Path, PathBuf},
+	process::Command,
+};
+// --- custom ---
+use crate::{
+	bisect::{self, Date},
+	cargo_config::CargoConfig,
+	config::Config,
+	env_var::EnvVar,
+	error::{Error, Stage, WithPath},
+	host::{Arch, OS},
+	message::{Event, Output},
+	mirror::Mirror,
+	package::install_missing,
+	rust_toolchain::RustToolchain,
+	shell::Shell,
+	target::TargetSpec,
+	tool::Tool,
+	util::{resolve_path, run, run_with_excerpt, run_with_output},
+};
+
+/// The options of a build
+#[derive(Clone, Debug)]
+pub struct Options {
+	/// The project root, where the `Cargo.toml` is
+	pub root: PathBuf,
+	pub host: String,
+	pub host_os: OS,
+	/// `None` means build for the HOST, without `--target`
+	pub target: Option<String>,
+	/// The spec of the `target` from the registry
+	pub target_spec: Option<TargetSpec>,
+	/// The config file's, or the channel of `rust_toolchain`, or `nightly`
+	pub toolchain: String,
+	/// The `rust-toolchain(.toml)` of the project, its components and targets are installed as well
+	pub rust_toolchain: Option<RustToolchain>,
+	/// The toolchain of the wasm runtime, `None` means the same as `toolchain`
+	pub wasm_toolchain: Option<String>,
+	/// The Windows SDK/CRT folder (xwin layout) of the MSVC TARGETs
+	pub sdk: Option<PathBuf>,
+	pub wasm: bool,
+	pub pack: bool,
+	pub debug: bool,
+	pub verbose: bool,
+	/// Allow installing the missing toolchain/targets/tools, downloading deps and editing the cargo config file
+	pub install: bool,
+	/// Install the toolchains/targets/components from this rustup dist mirror
+	pub mirror: Option<Mirror>,
+	/// Fail instead of downloading the toolchains/targets/components from the network
+	pub offline: bool,
+	/// Where to set the linker of the TARGET, the global cargo config file only if it is asked for
+	pub cargo_config: CargoConfig,
+	/// Install the missing prerequisites with the package manager
+	pub install_missing: bool,
+	/// Answer yes to the package manager
+	pub yes: bool,
+	/// Print the install plan of the missing prerequisites only
+	pub dry_run: bool,
+	/// How the messages are printed
+	pub output: Output,
+	/// Keep the rustc errors of a failed build in its error, cargo shows no progress bar then, see `bisect-toolchain`
+	pub excerpt: bool,
+}
+
+impl Options {
+	/// Detect the HOST and apply the config file (and its `[target.<triple>]` overrides) of the TARGET
+	pub fn new(config: &Config, target: Option<String>) -> Result<Self, Error> {
+		let profile = config.profile(target.as_deref());
+		let host_os = OS::detect()?;
+		let host = if let Some(host) = &config.host {
+			host.to_owned()
+		} else {
+			Arch::detect()?.triple(&host_os)
+		};
+		let target_spec = if let Some(target) = &target {
+			Some(
+				config
+					.registry
+					.get(target)
+					.cloned()
+					.ok_or_else(|| Error::UnsupportedTarget {
+						target: target.to_owned(),
+						host: host.clone(),
+					})?,
+			)
+		} else {
+			None
+		};
+
+		let root = match &config.root {
+			Some(root) => root.to_owned(),
+			None => env::current_dir()?,
+		};
+		let rust_toolchain = RustToolchain::discover(&root)?;
+		// relative to the config file
+		let config_dir = config.path.as_ref().and_then(|path| path.parent()).unwrap_or(&root);
+		let mirror = config
+			.rustup_mirror
+			.as_ref()
+			.map(|mirror| Mirror::new(mirror, config_dir));
+		let sdk = profile.sdk.as_ref().map(|sdk| resolve_path(sdk, config_dir));
+
+		Ok(Self {
+			root,
+			host,
+			host_os,
+			target,
+			target_spec,
+			toolchain: profile
+				.toolchain
+				.or_else(|| {
+					rust_toolchain
+						.as_ref()
+						.map(|rust_toolchain| rust_toolchain.channel.clone())
+				})
+				.unwrap_or_else(|| String::from("nightly")),
+			rust_toolchain,
+			wasm_toolchain: profile.wasm_toolchain,
+			sdk,
+			wasm: profile.wasm.unwrap_or(false),
+			pack: profile.pack.unwrap_or(false),
+			debug: profile.debug.unwrap_or(false),
+			verbose: profile.verbose.unwrap_or(false),
+			install: true,
+			mirror,
+			offline: config.offline,
+			cargo_config: config.cargo_config.unwrap_or(CargoConfig::Env),
+			install_missing: false,
+			yes: false,
+			dry_run: false,
+			output: Output::default(),
+			excerpt: false,
+		})
+	}
+
+	pub fn is_cross_compile(&self) -> bool {
+		self.target.is_some()
+	}
+
+	pub fn run_target(&self) -> &str {
+		self.target.as_ref().unwrap_or(&self.host)
+	}
+
+	pub fn wasm_toolchain(&self) -> &str {
+		self.wasm_toolchain.as_ref().unwrap_or(&self.toolchain)
+	}
+
+	/// The TARGET links everything statically, see `static` in `targets.toml`
+	pub fn is_static(&self) -> bool {
+		self.target_spec.as_ref().is_some_and(|spec| spec.static_link)
+	}
+
+	/// Attach the stage and the TARGET to the error of `f`
+	pub fn stage<T, F: FnOnce() -> Result<T, Error>>(&self, stage: Stage, f: F) -> Result<T, Error> {
+		f().map_err(|e| e.in_stage(stage, self.run_target()))
+	}
+}
+
+/// What to run for a TARGET, the subcommands of the command line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Task {
+	/// Check, build and pack (with `options.pack`) in one pass
+	All,
+	/// Download the deps and set up the cargo config file
+	Deps,
+	/// Build the project, and the wasm with `options.wasm`
+	Build,
+	/// Build the wasm only
+	Wasm,
+	/// Pack the previously built project without rebuilding
+	Pack,
+	/// Remove the packed artifacts, the downloaded deps as well with `deps`
+	Clean { deps: bool },
+	/// Print the env vars as shell exports, `None` follows `SHELL`
+	Env { shell: Option<Shell> },
+	/// Find the first nightly in `(good, bad]` which fails to build, see `bisect::bisect`
+	Bisect { good: Date, bad: Date },
+}
+
+impl Task {
+	/// The TARGET is required to pack, to clean the packed artifacts and for the deps of course
+	pub fn requires_target(self, options: &Options) -> bool {
+		match self {
+			Task::All => options.pack,
+			Task::Deps | Task::Pack | Task::Clean { .. } | Task::Env { .. } => true,
+			Task::Build | Task::Wasm | Task::Bisect { .. } => false,
+		}
+	}
+
+	/// `pack` and `clean` never install anything, so they skip the install plan
+	fn installs(self) -> bool {
+		!matches!(self, Task::Pack | Task::Clean { .. })
+	}
+}
+
+impl fmt::Display for Task {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			// without a subcommand
+			Task::All => write!(f, "build and pack"),
+			Task::Pack => write!(f, "pack"),
+			Task::Deps => write!(f, "deps"),
+			Task::Build => write!(f, "build"),
+			Task::Wasm => write!(f, "wasm"),
+			Task::Clean { .. } => write!(f, "clean"),
+			Task::Env { .. } => write!(f, "env"),
+			Task::Bisect { .. } => write!(f, "bisect-toolchain"),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct Builder {
+	pub options: Options,
+	pub tool: Tool,
+	pub env_var: EnvVar,
+}
+
+impl Builder {
+	/// Detect (and install, if `options.install` is set) the tools and the env vars of the TARGET
+	pub fn new(options: Options) -> Result<Self, Error> {
+		Ok(Self {
+			tool: options.stage(Stage::Toolchain, || Tool::new(&options))?,
+			env_var: options.stage(Stage::Deps, || EnvVar::new(&options))?,
+			options,
+		})
+	}
+
+	/// Run the task for the TARGET, with `options.install_missing` (or `options.dry_run`)
+	/// the missing prerequisites are installed (or the plan is printed) first
+	pub fn run(mut options: Options, task: Task) -> Result<(), Error> {
+		if task.requires_target(&options) && options.target.is_none() {
+			return Err(Error::Usage(format!(
+				"{} requires a TARGET, pass `--target` or set `targets`",
+				task
+			)));
+		}
+		if task.installs() && !install_missing(&options)? {
+			return Ok(());
+		}
+
+		match task {
+			Task::Clean { deps } => clean(&options, deps),
+			Task::Bisect { good, bad } => bisect::bisect(&options, good, bad),
+			Task::Deps => options.stage(Stage::Deps, || EnvVar::new(&options))?.check(&options),
+			Task::Env { shell } => {
+				// only print, `deps` downloads the deps and writes the cargo config file
+				options.install = false;
+				let env_var = options.stage(Stage::Deps, || EnvVar::new(&options))?;
+				env_var.check(&options)?;
+
+				let shell = shell.unwrap_or_else(Shell::detect);
+				let exports = env_var.exports(&options);
+				options.output.emit(Event::Exports {
+					target: options.run_target(),
+					shell: &shell.to_string(),
+					vars: &exports,
+				});
+				if !options.output.is_json() {
+					for (k, v) in &exports {
+						println!("{}", shell.export(k, v));
+					}
+				}
+
+				Ok(())
+			}
+			Task::Pack => {
+				options.install = false;
+
+				Builder::new(options)?.pack()
+			}
+			Task::All | Task::Build | Task::Wasm => {
+				// the wasm tools are checked with `options.wasm` only
+				if task == Task::Wasm {
+					options.wasm = true;
+				}
+				let builder = Builder::new(options)?;
+				builder.check()?;
+
+				match task {
+					Task::Wasm => builder.build_wasm(),
+					Task::Build => builder.build(),
+					_ => {
+						builder.build()?;
+						if builder.options.pack {
+							builder.pack()?;
+						}
+
+						Ok(())
+					}
+				}
+			}
+		}
+	}
+
+	/// Fail with the missing tools/env vars, if any
+	pub fn check(&self) -> Result<(), Error> {
+		let Builder {
+			options,
+			tool:
+				Tool {
+					rustup,
+					cargo,
+					toolchain,
+					wasm_toolchain,
+					run_target,
+					wasm_target,
+					wasm_gc,
+					pinned,
+				},
+			env_var,
+		} = self;
+		let missing = |stage: Stage, items: &[(&str, &str)]| {
+			let missing = items
+				.iter()
+				.filter(|(_, v)| v.is_empty())
+				.map(|(k, _)| (*k).to_owned())
+				.collect::<Vec<_>>();
+			if missing.is_empty() {
+				Ok(())
+			} else {
+				Err(Error::Missing(missing).in_stage(stage, options.run_target()))
+			}
+		};
+
+		let mut items = vec![
+			("rustup", rustup.as_str()),
+			("cargo", cargo.as_str()),
+			("toolchain", toolchain.as_str()),
+			("run target", run_target.as_str()),
+		];
+		if options.wasm {
+			items.extend([
+				("wasm toolchain", wasm_toolchain.as_str()),
+				("wasm target", wasm_target.as_str()),
+				("wasm-gc", wasm_gc.as_str()),
+			]);
+		}
+		items.extend(pinned.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+		missing(Stage::Toolchain, &items)?;
+
+		env_var.check(options)
+	}
+
+	/// Build the wasm (with `options.wasm`) and the project
+	pub fn build(&self) -> Result<(), Error> {
+		if self.options.wasm {
+			self.build_wasm()?;
+		}
+
+		self.build_native()
+	}
+
+	/// Build the project only, checked to be static for the musl TARGETs
+	pub fn build_native(&self) -> Result<(), Error> {
+		self.options.stage(Stage::Build, || {
+			self.options.output.build(self.options.run_target(), "native", || self.build_project())?;
+			if self.options.is_static() {
+				self.check_static()?;
+			}
+
+			Ok(())
+		})
+	}
+
+	/// Pack the built binary (with its ld-library if it is not static) into `target/<triple>-<project-name>.tar.gz`
+	pub fn pack(&self) -> Result<(), Error> {
+		self.options.stage(Stage::Pack, || self.pack_project())
+	}
+
+	pub fn build_wasm(&self) -> Result<(), Error> {
+		self.options.stage(Stage::Wasm, || {
+			self.options.output.build(self.options.run_target(), "wasm", || self.build_wasm_runtime())
+		})
+	}
+
+	/// The built binary of the project
+	pub(crate) fn binary_path(&self, package_name: &str) -> PathBuf {
+		let run_target = self.options.run_target();
+		let mut target_path = self.options.root.join("target");
+		target_path.push(run_target);
+		if self.options.debug {
+			target_path.push("debug");
+		} else {
+			target_path.push("release");
+		}
+		if run_target.contains("windows") {
+			target_path.push(format!("{}.exe", package_name));
+		} else {
+			target_path.push(package_name);
+		}
+
+		target_path
+	}
+
+	/// Make sure the static binary doesn't need any shared library
+	fn check_static(&self) -> Result<(), Error> {
+		let binary_path = self.binary_path(&package_name(&self.options.root)?);
+		let mut dynamic_section = None;
+		for readelf in ["readelf", "llvm-readelf"].iter() {
+			match run(Command::new(readelf).arg("-d").arg(&binary_path)) {
+				Ok(output) => {
+					dynamic_section = Some(output);
+					break;
+				}
+				Err(e) if e.is_not_found() => continue,
+				Err(e) => return Err(e),
+			}
+		}
+
+		// an unverified binary is not passed as a static one
+		let dynamic_section = dynamic_section.ok_or_else(|| {
+			Error::Missing(vec![String::from("readelf or llvm-readelf to verify the static binary")])
+		})?;
+		let needed = needed_libraries(&dynamic_section);
+		if !needed.is_empty() {
+			return Err(Error::Dynamic {
+				path: binary_path,
+				libraries: needed,
+			});
+		}
+
+		self.options.output.found("static", &binary_path.to_string_lossy());
+
+		Ok(())
+	}
+
+	fn pack_project(&self) -> Result<(), Error> {
+		let package_name = package_name(&self.options.root)?;
+		let binary_path = self.binary_path(&package_name);
+		if !binary_path.is_file() {
+			return Err(io::Error::new(io::ErrorKind::NotFound, "not found, build it first")).with_path(&binary_path);
+		}
+
+		// a static binary is self-contained
+		let ld_library_dir = if self.options.is_static() {
+			None
+		} else if self.env_var.deps.is_empty() {
+			return Err(Error::Missing(vec![String::from("deps")]));
+		} else {
+			Some(self.options.root.join(&self.env_var.deps).join("ld-library"))
+		};
+
+		pack_binary(
+			&self.options.output,
+			&self.options.root,
+			self.options.run_target(),
+			&package_name,
+			&binary_path,
+			ld_library_dir.as_deref(),
+		)
+	}
+
+	fn build_wasm_runtime(&self) -> Result<(), Error> {
+		let mut wasm_path = self.options.root.clone();
+		wasm_path.push("node/runtime/wasm");
+		if !wasm_path.is_dir() {
+			return Err(io::Error::new(io::ErrorKind::NotFound, "wasm runtime not found")).with_path(&wasm_path);
+		}
+
+		self.run_cargo(
+			Command::new("cargo")
+				.current_dir(&wasm_path)
+				.args([
+					&format!("+{}", self.tool.wasm_toolchain),
+					"rustc",
+					"--release",
+					"--target",
+					"wasm32-unknown-unknown",
+					"--",
+					"-C",
+					"link-arg=--export-table",
+				])
+				.env("CARGO_INCREMENTAL", "0"),
+		)?;
+		run(Command::new("wasm-gc").current_dir(&wasm_path).args([
+			"target/wasm32-unknown-unknown/release/node_runtime.wasm",
+			"target/wasm32-unknown-unknown/release/node_runtime.compact.wasm",
+		]))?;
+
+		Ok(())
+	}
+
+	/// Keep the excerpt of the errors with `options.excerpt`, show the progress of cargo otherwise
+	fn run_cargo(&self, command: &mut Command) -> Result<(), Error> {
+		if self.options.excerpt {
+			run_with_excerpt(command, &self.options.output)
+		} else {
+			run_with_output(command, &self.options.output)
+		}
+	}
+
+	fn build_project(&self) -> Result<(), Error> {
+		let mut build_command = Command::new("cargo");
+		build_command.current_dir(&self.options.root);
+
+		build_command.args([&format!("+{}", self.tool.toolchain), "rustc"]);
+		if !self.options.debug {
+			build_command.arg("--release");
+		}
+		if self.options.verbose {
+			build_command.arg("--verbose");
+		}
+		build_command.env("CARGO_INCREMENTAL", "1");
+		if let Some(target) = &self.options.target {
+			build_command.args(["--target", target]);
+			build_command.envs(self.env_var.cross_envs(&self.options));
+			// the cargo config file has them as `rustflags` otherwise
+			let rustc_args = self.env_var.rustflags(&self.options);
+			if self.options.cargo_config == CargoConfig::Env && !rustc_args.is_empty() {
+				build_command.arg("--").args(rustc_args);
+			}
+		}
+
+		self.run_cargo(&mut build_command)?;
+
+		Ok(())
+	}
+}
+
+/// Pack the binary (and the ld-library) into `target/<name>-<project-name>.tar.gz`
+pub(crate) fn pack_binary(
+	output: &Output,
+	root: &Path,
+	name: &str,
+	package_name: &str,
+	binary_path: &Path,
+	ld_library_dir: Option<&Path>,
+) -> Result<(), Error> {
+	let is_windows = name.contains("windows");
+	let target_dir = root.join("target");
+	let pack_dir = target_dir.join(format!("{}-{}", name, package_name));
+	if pack_dir.is_dir() {
+		fs::remove_dir_all(&pack_dir).with_path(&pack_dir)?;
+	}
+	fs::create_dir(&pack_dir).with_path(&pack_dir)?;
+
+	let pack_path = if is_windows {
+		pack_dir.join(format!("{}.exe", package_name))
+	} else {
+		pack_dir.join(package_name)
+	};
+	fs::copy(binary_path, &pack_path).with_path(binary_path)?;
+
+	if let Some(ld_library_dir) = ld_library_dir {
+		let mut copy_options = fs_extra::dir::CopyOptions::new();
+		copy_options.overwrite = true;
+		fs_extra::dir::copy(ld_library_dir, &pack_dir, &copy_options)
+			.map_err(|e| io::Error::other(e.to_string()))
+			.with_path(ld_library_dir)?;
+
+		if !is_windows {
+			let run_script_path = pack_dir.join("run.sh");
+			let mut run_script = fs::OpenOptions::new()
+				.create(true)
+				.truncate(true)
+				.write(true)
+				.open(&run_script_path)
+				.with_path(&run_script_path)?;
+			run_script
+				.write_all(run_script_of(name, package_name).as_bytes())
+				.and_then(|_| run_script.sync_all())
+				.with_path(&run_script_path)?;
+		}
+	}
+
+	run(Command::new("tar").current_dir(&target_dir).args([
+		"zcf",
+		&format!("{}-{}.tar.gz", name, package_name),
+		&format!("{}-{}", name, package_name),
+	]))?;
+	let artifact = target_dir.join(format!("{}-{}.tar.gz", name, package_name));
+	output.found("packed", &artifact.to_string_lossy());
+	output.emit(Event::Artifact {
+		target: name,
+		path: &artifact,
+	});
+
+	Ok(())
+}
+
+/// The `run.sh` of the pack, which points the dynamic loader of the TARGET to the packed `ld-library`
+fn run_script_of(name: &str, package_name: &str) -> String {
+	// dyld of macOS doesn't read `LD_LIBRARY_PATH`
+	let library_path = if name.contains("apple-darwin") {
+		"DYLD_LIBRARY_PATH"
+	} else {
+		"LD_LIBRARY_PATH"
+	};
+
+	format!(
+		"#!/usr/bin/env bash\nexport {0}=${0}:$(pwd)/ld-library\n./{1}",
+		library_path, package_name
+	)
+}
+
+/// The libraries of the `(NEEDED)` entries of `readelf -d`, empty for a static binary
+fn needed_libraries(dynamic_section: &str) -> Vec<String> {
+	dynamic_section
+		.lines()
+		.filter(|line| line.contains("(NEEDED)"))
+		.filter_map(|line| line.rsplit('[').next())
+		.map(|library| library.trim_end_matches(']').to_owned())
+		.collect()
+}
+
+/// The `package.name` of the project's `Cargo.toml`
+pub fn package_name(root: &Path) -> Result<String, Error> {
+	let path = root.join("Cargo.toml");
+	let mut cargo_toml = fs::File::open(&path).with_path(&path)?;
+	let mut config = String::new();
+	cargo_toml.read_to_string(&mut config).with_path(&path)?;
+
+	let config = config.parse::<toml::Value>().map_err(|e| Error::Manifest {
+		path: path.clone(),
+		reason: e.to_string(),
+	})?;
+
+	config
+		.get("package")
+		.and_then(|package| package.get("name"))
+		.and_then(|name| name.as_str())
+		.map(ToOwned::to_owned)
+		.ok_or(Error::Manifest {
+			path,
+			reason: String::from("missing `package.name`"),
+		})
+}
+
+/// Remove the packed <project-name> and <project-name>.tar.gz, also the downloaded deps if `deps` is set
+pub fn clean(options: &Options, deps: bool) -> Result<(), Error> {
+	options.stage(Stage::Pack, || {
+		let target = options.run_target();
+		let root_path = &options.root;
+		let package_name = package_name(root_path)?;
+		let mut paths = vec![
+			root_path.join("target").join(format!("{}-{}", target, package_name)),
+			root_path
+				.join("target")
+				.join(format!("{}-{}.tar.gz", target, package_name)),
+		];
+		if deps {
+			if let Some((folder, download_link)) = options.target_spec.as_ref().and_then(|spec| spec.deps.as_ref()) {
+				paths.push(root_path.join(folder));
+				if let Some(download_link) = download_link {
+					paths.push(root_path.join(download_link.rsplit('/').next().unwrap()));
+				}
+			}
+		}
+
+		for path in paths {
+			if path.is_dir() {
+				fs::remove_dir_all(&path).with_path(&path)?;
+			} else if path.is_file() {
+				fs::remove_file(&path).with_path(&path)?;
+			} else {
+				continue;
+			}
+
+			options.output.found("removed", &path.to_string_lossy());
+		}
+
+		Ok(())
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::TempDir;
+
+	#[test]
+	fn read_package_name() {
+		let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+		assert_eq!(package_name(root).unwrap(), "darwinia-builder");
+		assert!(package_name(&root.join("src")).is_err());
+	}
+
+	#[test]
+	fn library_path_of_run_script() {
+		assert_eq!(
+			run_script_of("x86_64-unknown-linux-gnu", "node"),
+			"#!/usr/bin/env bash\nexport LD_LIBRARY_PATH=$LD_LIBRARY_PATH:$(pwd)/ld-library\n./node"
+		);
+		assert_eq!(
+			run_script_of("universal-apple-darwin", "node"),
+			"#!/usr/bin/env bash\nexport DYLD_LIBRARY_PATH=$DYLD_LIBRARY_PATH:$(pwd)/ld-library\n./node"
+		);
+		assert!(run_script_of("aarch64-apple-darwin", "node").contains("export DYLD_LIBRARY_PATH="));
+	}
+
+	#[test]
+	fn readelf_needed() {
+		let dynamic = r#"
+Dynamic section at offset 0x2d6e8 contains 28 entries:
+  Tag        Type                         Name/Value
+ 0x0000000000000001 (NEEDED)             Shared library: [libstdc++.so.6]
+ 0x0000000000000001 (NEEDED)             Shared library: [libc.so.6]
+ 0x000000000000000c (INIT)               0x5000
+ 0x000000000000001d (RUNPATH)            Library runpath: [$ORIGIN]
+"#;
+		assert_eq!(needed_libraries(dynamic), ["libstdc++.so.6", "libc.so.6"]);
+
+		let r#static = r#"
+Dynamic section at offset 0x1ec0e8 contains 20 entries:
+  Tag        Type                         Name/Value
+ 0x000000000000000c (INIT)               0x1000
+ 0x000000006ffffffb (FLAGS_1)            Flags: PIE
+"#;
+		assert!(needed_libraries(r#static).is_empty());
+		assert!(needed_libraries("\nThere is no dynamic section in this file.\n").is_empty());
+	}
+
+	#[test]
+	fn separate_wasm_toolchain() {
+		let dir = TempDir::new("separate-wasm-toolchain");
+		let path = dir.join("darwinia-builder.toml");
+		fs::write(
+			&path,
+			r#"
+				toolchain = "stable"
+				wasm-toolchain = "nightly-2020-10-06"
+
+				[target.x86_64-pc-windows-gnu]
+				wasm-toolchain = "nightly-2020-09-01"
+
+				[target.aarch64-unknown-linux-gnu]
+				toolchain = "nightly-2020-08-01"
+			"#,
+		)
+		.unwrap();
+		let config = Config::load(&path).unwrap();
+		let options = |target: &str| Options::new(&config, Some(target.to_owned())).unwrap();
+
+		let options_ = options("x86_64-unknown-linux-gnu");
+		assert_eq!(options_.toolchain, "stable");
+		assert_eq!(options_.wasm_toolchain(), "nightly-2020-10-06");
+		let options_ = options("x86_64-pc-windows-gnu");
+		assert_eq!(options_.toolchain, "stable");
+		assert_eq!(options_.wasm_toolchain(), "nightly-2020-09-01");
+		// the native toolchain of a TARGET doesn't change the wasm one
+		let options_ = options("aarch64-unknown-linux-gnu");
+		assert_eq!(options_.toolchain, "nightly-2020-08-01");
+		assert_eq!(options_.wasm_toolchain(), "nightly-2020-10-06");
+
+		// the same as the native one if not set
+		let mut options = Options::new(&Config::default(), None).unwrap();
+		options.toolchain = String::from("nightly-2020-10-06");
+		assert_eq!(options.wasm_toolchain(), "nightly-2020-10-06");
+
+		fs::write(&path, "wasm-toolchain = 2020\n").unwrap();
+		assert!(Config::load(&path).unwrap_err().to_string().contains("wasm-toolchain"));
+	}
+
+	#[test]
+	fn task_requires_target() {
+		let mut options = Options::new(&Config::default(), None).unwrap();
+		assert!(!Task::All.requires_target(&options));
+		assert!(!Task::Build.requires_target(&options));
+		options.pack = true;
+		assert!(Task::All.requires_target(&options));
+
+		let e = Builder::run(options.clone(), Task::All).unwrap_err();
+		assert_eq!(e.to_string(), "build and pack requires a TARGET, pass `--target` or set `targets`");
+		let e = Builder::run(options, Task::Clean { deps: false }).unwrap_err();
+		assert_eq!(e.to_string(), "clean requires a TARGET, pass `--target` or set `targets`");
+		assert_eq!(Task::Pack.to_string(), "pack");
+	}
+}
@@ -0,0 +1,605 @@
+// --- std ---
+use std::{
+	fmt, fs,
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::OnceLock,
+	time::{SystemTime, UNIX_EPOCH},
+};
+// --- external ---
+use toml_edit::{value, Array, DocumentMut, Item, Table};
+// --- custom ---
+use crate::{
+	error::{Error, WithPath},
+	message::Output,
+};
+
+/// The `-C` flags of `rustflags` which the builder owns, the rest of them are the user's
+const OWNED_RUSTFLAGS: &[&str] = &["link_args=--sysroot=", "target-feature=+crt-static"];
+
+/// The values of `--cargo-config`
+pub const CARGO_CONFIGS: &[&str] = &["env", "project", "global"];
+
+/// The backup of a cargo config file is `<file>.<milliseconds>.bak`
+const BACKUP: &str = "bak";
+/// The cargo config file didn't exist before, `<file>.<milliseconds>.new` is an empty marker
+const CREATED: &str = "new";
+/// What the builder wrote after the backup, `<file>.<milliseconds>.written`, to tell if the file is edited since
+const WRITTEN: &str = "written";
+
+/// Where the linker of the TARGET is set for cargo
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CargoConfig {
+	/// `CARGO_TARGET_<TRIPLE>_LINKER` of each cargo invocation, nothing is written
+	Env,
+	/// The `.cargo/config.toml` of the project
+	Project,
+	/// `~/.cargo/config.toml`, it affects every project of the user
+	Global,
+}
+
+impl CargoConfig {
+	/// The file to edit, `None` for `Env`
+	///
+	/// The legacy `config` is edited if it exists since cargo prefers it over `config.toml`
+	pub fn path(self, root: &Path) -> Option<PathBuf> {
+		let dir = match self {
+			CargoConfig::Env => return None,
+			CargoConfig::Project => root.join(".cargo"),
+			CargoConfig::Global => dirs::home_dir().unwrap_or_default().join(".cargo"),
+		};
+		let legacy = dir.join("config");
+
+		Some(if legacy.is_file() {
+			legacy
+		} else {
+			dir.join("config.toml")
+		})
+	}
+}
+
+impl FromStr for CargoConfig {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"env" => Ok(CargoConfig::Env),
+			"project" => Ok(CargoConfig::Project),
+			"global" => Ok(CargoConfig::Global),
+			_ => Err(format!(
+				"unknown cargo config `{}`, expect one of {}",
+				s,
+				CARGO_CONFIGS.join(", ")
+			)),
+		}
+	}
+}
+
+impl fmt::Display for CargoConfig {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			CargoConfig::Env => write!(f, "env"),
+			CargoConfig::Project => write!(f, "project"),
+			CargoConfig::Global => write!(f, "global"),
+		}
+	}
+}
+
+/// The env var which sets the linker of the TARGET e.g. `CARGO_TARGET_X86_64_PC_WINDOWS_GNU_LINKER`
+pub fn linker_env(triple: &str) -> String {
+	format!("CARGO_TARGET_{}_LINKER", triple.to_uppercase().replace(['-', '.'], "_"))
+}
+
+/// The env var which sets the rustc flags of the TARGET e.g. `CARGO_TARGET_X86_64_PC_WINDOWS_GNU_RUSTFLAGS`
+pub fn rustflags_env(triple: &str) -> String {
+	format!(
+		"CARGO_TARGET_{}_RUSTFLAGS",
+		triple.to_uppercase().replace(['-', '.'], "_")
+	)
+}
+
+/// The keys of `[target.<triple>]` which the builder sets
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TargetConfig {
+	pub linker: String,
+	pub ar: Option<String>,
+	/// Replace the builder's own flags (`OWNED_RUSTFLAGS`) of the config, the user's are kept, left as it is if empty
+	pub rustflags: Vec<String>,
+}
+
+impl TargetConfig {
+	/// The one-line summary e.g. `[target.<triple>] linker = "gcc"`
+	pub fn line(&self, triple: &str) -> String {
+		let mut line = format!("[target.{}] linker = {:?}", triple, self.linker);
+		if let Some(ar) = &self.ar {
+			line.push_str(&format!(" ar = {:?}", ar));
+		}
+		if !self.rustflags.is_empty() {
+			line.push_str(&format!(" rustflags = {:?}", self.rustflags));
+		}
+
+		line
+	}
+
+	/// Set the keys in the `[target.<triple>]` of the config (`[target."<triple>"]` as well),
+	/// the rest of it is kept as it is, comments included
+	pub fn merge(&self, config: &str, triple: &str) -> Result<String, String> {
+		let mut document = config.parse::<DocumentMut>().map_err(|e| e.to_string())?;
+		let targets = document
+			.entry("target")
+			.or_insert_with(|| {
+				let mut targets = Table::new();
+				targets.set_implicit(true);
+
+				Item::Table(targets)
+			})
+			.as_table_like_mut()
+			.ok_or_else(|| String::from("`target` is not a table"))?;
+		let target = targets
+			.entry(triple)
+			.or_insert(Item::Table(Table::new()))
+			.as_table_like_mut()
+			.ok_or_else(|| format!("`target.{}` is not a table", triple))?;
+
+		let mut set = |key: &str, new: &str| {
+			// keep the untouched keys with their comments, the values are compared without the decor
+			if target.get(key).and_then(Item::as_str) != Some(new) {
+				target.insert(key, value(new));
+			}
+		};
+		set("linker", &self.linker);
+		if let Some(ar) = &self.ar {
+			set("ar", ar);
+		}
+		if !self.rustflags.is_empty() {
+			// `rustflags = "-C ..."` is an array as well for cargo
+			if let Some(rustflags) = target.get("rustflags").and_then(Item::as_str) {
+				let rustflags = rustflags.split_whitespace().collect::<Array>();
+				target.insert("rustflags", value(rustflags));
+			}
+			match target.get_mut("rustflags").and_then(Item::as_array_mut) {
+				Some(rustflags) => merge_rustflags(rustflags, &self.rustflags),
+				None => {
+					target.insert("rustflags", value(self.rustflags.iter().collect::<Array>()));
+				}
+			}
+		}
+
+		Ok(document.to_string())
+	}
+}
+
+/// Replace the `OWNED_RUSTFLAGS` of the array with the `flags`, untouched if they are the same already
+fn merge_rustflags(rustflags: &mut Array, flags: &[String]) {
+	let old = rustflags.iter().map(|flag| flag.as_str()).collect::<Vec<_>>();
+	let owned =
+		|flag: Option<&str>| flag.is_some_and(|flag| OWNED_RUSTFLAGS.iter().any(|owned| flag.starts_with(owned)));
+	// both `"-C", "<flag>"` and `"-C<flag>"`
+	let mut removed = vec![];
+	for (i, flag) in old.iter().enumerate() {
+		match flag {
+			Some("-C") if owned(old.get(i + 1).copied().flatten()) => removed.extend([i, i + 1]),
+			Some(flag) if owned(flag.strip_prefix("-C")) => removed.push(i),
+			_ => (),
+		}
+	}
+
+	let new = old
+		.iter()
+		.enumerate()
+		.filter(|(i, _)| !removed.contains(i))
+		.map(|(_, flag)| *flag)
+		.chain(flags.iter().map(|flag| Some(flag.as_str())))
+		.collect::<Vec<_>>();
+	if new == old {
+		return;
+	}
+
+	for i in removed.into_iter().rev() {
+		rustflags.remove(i);
+	}
+	for flag in flags {
+		rustflags.push(flag.as_str());
+	}
+}
+
+/// Merge the keys of the TARGET into the cargo config file, back it up first if it is changed
+///
+/// Return the summary of the keys, empty if they are not set and `install` is not allowed
+pub(crate) fn set_target(
+	triple: &str,
+	target_config: &TargetConfig,
+	path: &Path,
+	install: bool,
+	output: &Output,
+) -> Result<String, Error> {
+	let line = target_config.line(triple);
+	let config = if path.is_file() {
+		fs::read_to_string(path).with_path(path)?
+	} else {
+		String::new()
+	};
+	let merged = target_config.merge(&config, triple).map_err(|reason| Error::Manifest {
+		path: path.to_path_buf(),
+		reason,
+	})?;
+
+	if merged != config {
+		if !install {
+			output.env(triple, "config file", Err(&line));
+
+			return Ok(String::new());
+		}
+
+		output.missing("config file", &format!("will be updated: {}", path.to_string_lossy()));
+
+		let written = backup(path, output)?;
+		fs::write(path, &merged).with_path(path)?;
+		fs::write(&written, &merged).with_path(&written)?;
+	}
+
+	output.env(triple, "config file", Ok(&line));
+
+	Ok(line)
+}
+
+/// Undo the last run of the builder: restore the project's and the global cargo config files it changed,
+/// run it again to go further back
+///
+/// Nothing is restored if any of the files is edited since the builder wrote it, its backup must be restored by hand
+pub fn undo(root: &Path, output: &Output) -> Result<(), Error> {
+	undo_in(
+		&[root.join(".cargo"), dirs::home_dir().unwrap_or_default().join(".cargo")],
+		output,
+	)
+}
+
+/// Undo the last run of the builder in the `.cargo` dirs
+fn undo_in(dirs: &[PathBuf], output: &Output) -> Result<(), Error> {
+	let mut latest = vec![];
+	for dir in dirs {
+		// either might be edited, depending on which one existed at that time
+		for name in ["config", "config.toml"] {
+			let path = dir.join(name);
+			if let Some((milliseconds, backup)) = backups(&path)?.pop() {
+				latest.push((milliseconds, path, backup));
+			}
+		}
+	}
+	// the backups of a run share its timestamp
+	let run = match latest.iter().map(|(milliseconds, ..)| *milliseconds).max() {
+		Some(run) => run,
+		None => {
+			output.found("config file", "nothing to undo");
+
+			return Ok(());
+		}
+	};
+	let group = latest
+		.into_iter()
+		.filter(|(milliseconds, ..)| *milliseconds == run)
+		.map(|(_, path, backup)| (path, backup))
+		.collect::<Vec<_>>();
+
+	for (path, backup) in &group {
+		let written = backup.with_extension(WRITTEN);
+		// a created file which is removed already is restored
+		if is_created(backup) && !path.exists() {
+			continue;
+		}
+		if written.is_file()
+			&& fs::read_to_string(path).unwrap_or_default() != fs::read_to_string(&written).with_path(&written)?
+		{
+			let reason = if is_created(backup) {
+				String::from("edited since the builder created it, remove it by hand")
+			} else {
+				format!(
+					"edited since the builder changed it, restore {} by hand",
+					backup.to_string_lossy()
+				)
+			};
+
+			return Err(Error::Manifest {
+				path: path.to_owned(),
+				reason,
+			});
+		}
+	}
+
+	for (path, backup) in &group {
+		if is_created(backup) {
+			if path.exists() {
+				fs::remove_file(path).with_path(path)?;
+			}
+			output.found("config file", &format!("removed {}", path.to_string_lossy()));
+		} else {
+			fs::copy(backup, path).with_path(path)?;
+			output.found(
+				"config file",
+				&format!("restored {} from {}", path.to_string_lossy(), backup.to_string_lossy()),
+			);
+		}
+		fs::remove_file(backup).with_path(backup)?;
+		let written = backup.with_extension(WRITTEN);
+		if written.is_file() {
+			fs::remove_file(&written).with_path(&written)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// The timestamp of this run of the builder, shared by all of its backups
+fn run_timestamp() -> u128 {
+	static RUN: OnceLock<u128> = OnceLock::new();
+
+	*RUN.get_or_init(|| {
+		SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|duration| duration.as_millis())
+			.unwrap_or_default()
+	})
+}
+
+/// Copy the file to `<file>.<milliseconds>.bak`, or leave a `<file>.<milliseconds>.new` marker if it doesn't exist
+///
+/// The file is backed up once per run, the first backup is the state before the run.
+/// Return the path of its `<file>.<milliseconds>.written`
+fn backup(path: &Path, output: &Output) -> Result<PathBuf, Error> {
+	let milliseconds = run_timestamp();
+	let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+	let sibling = |extension: &str| path.with_file_name(format!("{}.{}.{}", file_name, milliseconds, extension));
+
+	if sibling(BACKUP).is_file() || sibling(CREATED).is_file() {
+		// changed by another TARGET of this run
+	} else if path.is_file() {
+		let backup = sibling(BACKUP);
+		fs::copy(path, &backup).with_path(&backup)?;
+		output.found("config backup", &backup.to_string_lossy());
+	} else {
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir).with_path(dir)?;
+		}
+		let marker = sibling(CREATED);
+		fs::write(&marker, "").with_path(&marker)?;
+	}
+
+	Ok(sibling(WRITTEN))
+}
+
+/// The backup is a marker of the file created by the builder
+fn is_created(backup: &Path) -> bool {
+	backup.extension().is_some_and(|extension| extension == CREATED)
+}
+
+/// The timestamp of `<file>.<milliseconds>.bak` or `<file>.<milliseconds>.new`, `None` for the other files
+fn backup_timestamp(file_name: &str, name: &str) -> Option<u128> {
+	name.strip_prefix(&format!("{}.", file_name))
+		.and_then(|rest| {
+			rest.strip_suffix(&format!(".{}", BACKUP))
+				.or_else(|| rest.strip_suffix(&format!(".{}", CREATED)))
+		})
+		.and_then(|milliseconds| milliseconds.parse::<u128>().ok())
+}
+
+/// The backups (and the markers) of the file with their timestamps, the oldest first
+fn backups(path: &Path) -> Result<Vec<(u128, PathBuf)>, Error> {
+	let (dir, file_name) = match (path.parent(), path.file_name()) {
+		(Some(dir), Some(file_name)) if dir.is_dir() => (dir, file_name.to_string_lossy()),
+		_ => return Ok(vec![]),
+	};
+	let mut backups = vec![];
+	for entry in fs::read_dir(dir).with_path(dir)? {
+		let entry = entry.with_path(dir)?;
+		if let Some(milliseconds) = backup_timestamp(&file_name, &entry.file_name().to_string_lossy()) {
+			backups.push((milliseconds, entry.path()));
+		}
+	}
+	backups.sort();
+
+	Ok(backups)
+}
+
+#[cfg(test)]
+mod tests {
+	// --- custom ---
+	use super::*;
+	use crate::util::TempDir;
+
+	fn undo_dir(dir: &Path) -> Result<(), Error> {
+		undo_in(&[dir.to_path_buf()], &Output::default())
+	}
+
+	/// Leave the files of a run as `backup` does, `before` is `None` for a created file
+	fn run(path: &Path, milliseconds: u128, before: Option<&str>, written: &str) {
+		let sibling = |extension: &str| {
+			let file_name = path.file_name().unwrap().to_string_lossy();
+
+			path.with_file_name(format!("{}.{}.{}", file_name, milliseconds, extension))
+		};
+		match before {
+			Some(before) => fs::write(sibling(BACKUP), before).unwrap(),
+			None => fs::write(sibling(CREATED), "").unwrap(),
+		}
+		fs::write(sibling(WRITTEN), written).unwrap();
+		fs::write(path, written).unwrap();
+	}
+
+	#[test]
+	fn target_env_names() {
+		assert_eq!(
+			linker_env("x86_64-pc-windows-gnu"),
+			"CARGO_TARGET_X86_64_PC_WINDOWS_GNU_LINKER"
+		);
+		// osxcross's triples have the Darwin version
+		assert_eq!(
+			linker_env("x86_64-apple-darwin20.4"),
+			"CARGO_TARGET_X86_64_APPLE_DARWIN20_4_LINKER"
+		);
+		assert_eq!(
+			rustflags_env("armv7-unknown-linux-gnueabihf"),
+			"CARGO_TARGET_ARMV7_UNKNOWN_LINUX_GNUEABIHF_RUSTFLAGS"
+		);
+	}
+
+	#[test]
+	fn backup_names() {
+		assert_eq!(backup_timestamp("config.toml", "config.toml.1700000000000.bak"), Some(1700000000000));
+		assert_eq!(backup_timestamp("config.toml", "config.toml.1700000000000.new"), Some(1700000000000));
+		assert_eq!(backup_timestamp("config", "config.1700000000000.bak"), Some(1700000000000));
+		assert_eq!(backup_timestamp("config.toml", "config.toml.1700000000000.written"), None);
+		assert_eq!(backup_timestamp("config", "config.toml.1700000000000.bak"), None);
+		assert_eq!(backup_timestamp("config.toml", "config.toml.bak"), None);
+		assert_eq!(backup_timestamp("config.toml", "config.toml.latest.bak"), None);
+		assert_eq!(backup_timestamp("config.toml", "config.toml"), None);
+	}
+
+	#[test]
+	fn undo_by_run() {
+		let dir = TempDir::new("undo-by-run");
+		let config_toml = dir.join("config.toml");
+		let config = dir.join("config");
+		run(&config_toml, 100, Some("# mine\n"), "# first\n");
+		run(&config_toml, 200, Some("# first\n"), "# second\n");
+		run(&config, 200, None, "# created\n");
+
+		undo_dir(&dir).unwrap();
+		assert_eq!(fs::read_to_string(&config_toml).unwrap(), "# first\n");
+		assert!(!config.exists());
+		assert_eq!(backups(&config).unwrap(), vec![]);
+		assert_eq!(backups(&config_toml).unwrap(), vec![(100, dir.join("config.toml.100.bak"))]);
+		assert!(!dir.join("config.toml.200.written").exists());
+
+		undo_dir(&dir).unwrap();
+		assert_eq!(fs::read_to_string(&config_toml).unwrap(), "# mine\n");
+		assert_eq!(backups(&config_toml).unwrap(), vec![]);
+		assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+		// nothing left to undo
+		undo_dir(&dir).unwrap();
+		assert_eq!(fs::read_to_string(&config_toml).unwrap(), "# mine\n");
+	}
+
+	#[test]
+	fn refuse_edited() {
+		let dir = TempDir::new("refuse-edited");
+		let config_toml = dir.join("config.toml");
+		let config = dir.join("config");
+		run(&config_toml, 100, Some("# mine\n"), "# builder\n");
+		fs::write(&config_toml, "# builder\n# edited\n").unwrap();
+		run(&config, 100, None, "# created\n");
+
+		let error = undo_dir(&dir).unwrap_err();
+		assert!(
+			error.to_string().contains("restore") && error.to_string().contains("config.toml.100.bak"),
+			"{}",
+			error
+		);
+		// nothing is restored, even the unedited file
+		assert_eq!(fs::read_to_string(&config_toml).unwrap(), "# builder\n# edited\n");
+		assert_eq!(fs::read_to_string(&config).unwrap(), "# created\n");
+		assert_eq!(backups(&config_toml).unwrap().len(), 1);
+		assert_eq!(backups(&config).unwrap().len(), 1);
+
+		fs::write(&config, "# created\n# edited\n").unwrap();
+		fs::write(&config_toml, "# builder\n").unwrap();
+		let error = undo_dir(&dir).unwrap_err();
+		assert!(error.to_string().contains("remove it by hand"), "{}", error);
+	}
+
+	#[test]
+	fn undo_removed_created_file() {
+		let dir = TempDir::new("undo-removed-created-file");
+		let config_toml = dir.join("config.toml");
+		run(&config_toml, 100, None, "# created\n");
+		fs::remove_file(&config_toml).unwrap();
+
+		undo_dir(&dir).unwrap();
+		assert!(!config_toml.exists());
+		// the marker and the written copy are removed as well
+		assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+	}
+
+	#[test]
+	fn keep_user_rustflags() {
+		let target_config = TargetConfig {
+			linker: String::from("gcc"),
+			ar: None,
+			rustflags: vec![String::from("-C"), String::from("link_args=--sysroot=/new")],
+		};
+		let config = r#"[target.aarch64-unknown-linux-gnu]
+linker = "gcc" # mine
+rustflags = ["-C", "target-cpu=native", "-C", "link_args=--sysroot=/old", "-Ctarget-feature=+crt-static"]
+"#;
+		let merged = target_config.merge(config, "aarch64-unknown-linux-gnu").unwrap();
+		assert_eq!(
+			merged,
+			r#"[target.aarch64-unknown-linux-gnu]
+linker = "gcc" # mine
+rustflags = ["-C", "target-cpu=native", "-C", "link_args=--sysroot=/new"]
+"#
+		);
+		assert_eq!(
+			target_config.merge(&merged, "aarch64-unknown-linux-gnu").unwrap(),
+			merged
+		);
+
+		let merged = target_config
+			.merge(
+				"[target.aarch64-unknown-linux-gnu]\nlinker = \"gcc\"\nrustflags = \"-C target-cpu=native\"\n",
+				"aarch64-unknown-linux-gnu",
+			)
+			.unwrap();
+		assert!(
+			merged.ends_with(
+				r#"rustflags = ["-C", "target-cpu=native", "-C", "link_args=--sysroot=/new"]
+"#
+			),
+			"{}",
+			merged
+		);
+	}
+
+	#[test]
+	fn merge_in_place() {
+		let target_config = TargetConfig {
+			linker: String::from("x86_64-w64-mingw32-gcc"),
+			ar: Some(String::from("x86_64-w64-mingw32-ar")),
+			rustflags: vec![],
+		};
+		let config = r#"# my settings
+[build]
+jobs = 4
+
+[target."x86_64-pc-windows-gnu"]
+linker = "gcc" # stale
+rustflags = ["-C", "opt-level=2"]
+"#;
+		let merged = target_config.merge(config, "x86_64-pc-windows-gnu").unwrap();
+		assert_eq!(
+			merged,
+			r#"# my settings
+[build]
+jobs = 4
+
+[target."x86_64-pc-windows-gnu"]
+linker = "x86_64-w64-mingw32-gcc"
+rustflags = ["-C", "opt-level=2"]
+ar = "x86_64-w64-mingw32-ar"
+"#
+		);
+		// nothing to change the second time
+		assert_eq!(target_config.merge(&merged, "x86_64-pc-windows-gnu").unwrap(), merged);
+		let commented = merged.replace("-ar\"", "-ar\" # mine");
+		assert_eq!(
+			target_config.merge(&commented, "x86_64-pc-windows-gnu").unwrap(),
+			commented
+		);
+
+		let merged = target_config.merge("", "x86_64-pc-windows-gnu").unwrap();
+		assert_eq!(
+			merged,
+			"[target.x86_64-pc-windows-gnu]\nlinker = \"x86_64-w64-mingw32-gcc\"\nar = \"x86_64-w64-mingw32-ar\"\n"
+		);
+	}
+}
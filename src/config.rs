@@ -0,0 +1,436 @@
+// --- std ---
+use std::{
+	collections::HashMap,
+	env, fmt, fs,
+	path::{Path, PathBuf},
+};
+// --- custom ---
+use crate::{
+	cargo_config::CargoConfig,
+	target::{Registry, TargetSpec},
+	universal::APPLE_TARGETS,
+};
+
+pub const CONFIG_FILE: &str = "darwinia-builder.toml";
+
+/// Project level configuration, read from `darwinia-builder.toml` next to `Cargo.toml`
+///
+/// ```toml
+/// toolchain = "nightly-2019-07-14"
+/// targets = ["x86_64-unknown-linux-gnu", "x86_64-pc-windows-gnu"]
+/// wasm = true
+/// pack = true
+/// # install the toolchains from a local rustup dist mirror, never from the network
+/// rustup-mirror = "/srv/rustup"
+/// offline = true
+///
+/// [target.x86_64-pc-windows-gnu]
+/// pack = false
+///
+/// # add a new TARGET, see `targets.toml` for the keys
+/// [target.powerpc64le-unknown-linux-gnu]
+/// linkers = ["powerpc64le-linux-gnu-gcc"]
+/// ```
+#[derive(Debug, Default)]
+pub struct Config {
+	pub path: Option<PathBuf>,
+	/// The folder of the nearest `Cargo.toml`, `None` if it is not found
+	pub root: Option<PathBuf>,
+	pub host: Option<String>,
+	pub targets: Vec<String>,
+	/// Build the Apple TARGETs into a universal binary, same as `--universal`
+	pub universal: bool,
+	/// The rustup dist mirror, a folder (relative to the config file) or a URL, same as `--rustup-mirror`
+	pub rustup_mirror: Option<String>,
+	/// Never download the toolchains from the network, same as `--offline`
+	pub offline: bool,
+	/// Where to set the linker, same as `--cargo-config`
+	pub cargo_config: Option<CargoConfig>,
+	pub profile: Profile,
+	pub target: HashMap<String, Profile>,
+	/// The built-in TARGETs extended by the `[target.<triple>]` specs
+	pub registry: Registry,
+}
+
+impl Config {
+	/// Look for the nearest `Cargo.toml` upwards and load the config file beside it (if any)
+	pub fn discover() -> Result<Self, ConfigError> {
+		let current_dir = env::current_dir().map_err(|e| ConfigError::new(CONFIG_FILE, None, e.to_string()))?;
+
+		for dir in current_dir.ancestors() {
+			if dir.join("Cargo.toml").is_file() {
+				let path = dir.join(CONFIG_FILE);
+				let mut config = if path.is_file() {
+					Self::load(&path)?
+				} else {
+					Self::default()
+				};
+				config.root = Some(dir.to_path_buf());
+
+				return Ok(config);
+			}
+		}
+
+		Ok(Self::default())
+	}
+
+	pub fn load(path: &Path) -> Result<Self, ConfigError> {
+		let content = fs::read_to_string(path).map_err(|e| ConfigError::new(path, None, e.to_string()))?;
+		let mut config = Self::parse(&content).map_err(|(key, reason)| ConfigError::new(path, key, reason))?;
+		config.path = Some(path.to_path_buf());
+
+		Ok(config)
+	}
+
+	fn parse(content: &str) -> Result<Self, (Option<String>, String)> {
+		let value = content.parse::<toml::Value>().map_err(|e| (None, e.to_string()))?;
+		let table = value.as_table().ok_or((None, String::from("expect a table")))?;
+		let mut config = Self::default();
+
+		for (key, value) in table {
+			match key.as_str() {
+				"host" => config.host = Some(target_triple(key, value)?),
+				"targets" => {
+					let targets = value
+						.as_array()
+						.ok_or((Some(key.to_owned()), String::from("expect an array of target triples")))?;
+					for target in targets {
+						config.targets.push(target_triple(key, target)?);
+					}
+				}
+				"universal" => {
+					config.universal = value
+						.as_bool()
+						.ok_or((Some(key.to_owned()), String::from("expect a boolean")))?
+				}
+				"rustup-mirror" => {
+					config.rustup_mirror = Some(
+						value
+							.as_str()
+							.ok_or((Some(key.to_owned()), String::from("expect a folder or a URL")))?
+							.to_owned(),
+					)
+				}
+				"cargo-config" => {
+					config.cargo_config = Some(
+						value
+							.as_str()
+							.ok_or_else(|| String::from("expect a string"))
+							.and_then(str::parse)
+							.map_err(|reason| (Some(key.to_owned()), reason))?,
+					)
+				}
+				"offline" => {
+					config.offline = value
+						.as_bool()
+						.ok_or((Some(key.to_owned()), String::from("expect a boolean")))?
+				}
+				"target" => {
+					let targets = value.as_table().ok_or((
+						Some(key.to_owned()),
+						String::from("expect a table of `[target.<triple>]`"),
+					))?;
+					for (target, value) in targets {
+						let key = format!("target.{}", target);
+						let table = value
+							.as_table()
+							.ok_or((Some(key.clone()), String::from("expect a table")))?;
+						let mut profile = Profile::default();
+						let mut spec = config
+							.registry
+							.get(target)
+							.cloned()
+							.unwrap_or_else(|| TargetSpec::new(target));
+						for (k, v) in table {
+							let with_key = |(k, reason)| (Some(format!("{}.{}", key, k)), reason);
+							if !profile.set(k, v).map_err(with_key)? && !spec.set(k, v).map_err(with_key)? {
+								return Err((Some(format!("{}.{}", key, k)), String::from("unknown key")));
+							}
+						}
+						if !spec.is_complete() {
+							return Err((
+								Some(key),
+								format!("unsupported target `{}`, set its `linkers` to add it", target),
+							));
+						}
+
+						config.registry.insert(spec);
+						config.target.insert(target.to_owned(), profile);
+					}
+				}
+				_ => {
+					if !config
+						.profile
+						.set(key, value)
+						.map_err(|(k, reason)| (Some(k), reason))?
+					{
+						return Err((Some(key.to_owned()), String::from("unknown key")));
+					}
+				}
+			}
+		}
+
+		// the TARGETs might be added after they are used, so check them at the end
+		for (key, target) in config
+			.host
+			.iter()
+			.map(|host| ("host", host))
+			.chain(config.targets.iter().map(|target| ("targets", target)))
+		{
+			if !config.registry.contains(target) {
+				return Err((Some(key.to_owned()), format!("unsupported target `{}`", target)));
+			}
+		}
+
+		Ok(config)
+	}
+
+	/// Whether to build the universal binary, and the TARGETs to build
+	///
+	/// `target` and `universal` are from the command line, they take precedence over the config file:
+	/// `--target` turns off the `universal` of the config file and replaces its `targets`
+	pub fn targets(&self, target: Option<&str>, universal: Option<bool>) -> (bool, Vec<Option<String>>) {
+		let universal = universal.unwrap_or(self.universal && target.is_none());
+		let targets = if universal {
+			APPLE_TARGETS.iter().map(|target| Some((*target).to_owned())).collect()
+		} else if let Some(target) = target {
+			vec![Some(target.to_owned())]
+		} else if self.targets.is_empty() {
+			vec![None]
+		} else {
+			self.targets.iter().cloned().map(Some).collect()
+		};
+
+		(universal, targets)
+	}
+
+	/// The global profile with the `[target.<triple>]` overrides applied
+	pub fn profile(&self, target: Option<&str>) -> Profile {
+		let mut profile = self.profile.clone();
+		if let Some(overrides) = target.and_then(|target| self.target.get(target)) {
+			profile.merge(overrides);
+		}
+
+		profile
+	}
+}
+
+/// The settings which can be overridden per target
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+	pub toolchain: Option<String>,
+	/// The toolchain of the wasm runtime, the same as `toolchain` if not set
+	pub wasm_toolchain: Option<String>,
+	pub wasm: Option<bool>,
+	pub pack: Option<bool>,
+	pub debug: Option<bool>,
+	pub verbose: Option<bool>,
+	/// The Windows SDK/CRT folder (xwin layout) of the MSVC TARGETs
+	pub sdk: Option<String>,
+}
+
+impl Profile {
+	/// Return `Ok(false)` if the key is not a profile key
+	fn set(&mut self, key: &str, value: &toml::Value) -> Result<bool, (String, String)> {
+		let boolean = |value: &toml::Value| {
+			value
+				.as_bool()
+				.map(Some)
+				.ok_or((key.to_owned(), String::from("expect a boolean")))
+		};
+
+		let toolchain = |value: &toml::Value| {
+			value.as_str().map(|toolchain| Some(toolchain.to_owned())).ok_or((
+				key.to_owned(),
+				String::from("expect a string e.g. \"nightly-2019-07-14\""),
+			))
+		};
+
+		match key {
+			"toolchain" => self.toolchain = toolchain(value)?,
+			"wasm-toolchain" => self.wasm_toolchain = toolchain(value)?,
+			"wasm" => self.wasm = boolean(value)?,
+			"pack" => self.pack = boolean(value)?,
+			"debug" => self.debug = boolean(value)?,
+			"verbose" => self.verbose = boolean(value)?,
+			"sdk" => {
+				self.sdk = Some(
+					value
+						.as_str()
+						.ok_or((key.to_owned(), String::from("expect a path string")))?
+						.to_owned(),
+				)
+			}
+			_ => return Ok(false),
+		}
+
+		Ok(true)
+	}
+
+	fn merge(&mut self, overrides: &Profile) {
+		for (field, value) in [
+			(&mut self.toolchain, &overrides.toolchain),
+			(&mut self.wasm_toolchain, &overrides.wasm_toolchain),
+			(&mut self.sdk, &overrides.sdk),
+		] {
+			if value.is_some() {
+				*field = value.clone();
+			}
+		}
+		for (field, value) in [
+			(&mut self.wasm, overrides.wasm),
+			(&mut self.pack, overrides.pack),
+			(&mut self.debug, overrides.debug),
+			(&mut self.verbose, overrides.verbose),
+		]
+		.iter_mut()
+		{
+			if value.is_some() {
+				**field = *value;
+			}
+		}
+	}
+}
+
+fn target_triple(key: &str, value: &toml::Value) -> Result<String, (Option<String>, String)> {
+	let target = value
+		.as_str()
+		.ok_or((Some(key.to_owned()), String::from("expect a target triple string")))?;
+
+	Ok(target.to_owned())
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+	pub path: PathBuf,
+	pub key: Option<String>,
+	pub reason: String,
+}
+
+impl ConfigError {
+	pub(crate) fn new<P: AsRef<Path>>(path: P, key: Option<String>, reason: String) -> Self {
+		Self {
+			path: path.as_ref().to_path_buf(),
+			key,
+			reason,
+		}
+	}
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		if let Some(key) = &self.key {
+			write!(f, "{}: `{}` {}", self.path.to_string_lossy(), key, self.reason)
+		} else {
+			write!(f, "{}: {}", self.path.to_string_lossy(), self.reason)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_with_overrides() {
+		let config = Config::parse(
+			r#"
+				toolchain = "nightly-2019-07-14"
+				targets = ["x86_64-unknown-linux-gnu", "x86_64-pc-windows-gnu"]
+				wasm = true
+
+				[target.x86_64-pc-windows-gnu]
+				wasm = false
+				debug = true
+				wasm-toolchain = "nightly-2019-08-01"
+			"#,
+		)
+		.unwrap();
+
+		assert_eq!(config.targets.len(), 2);
+
+		let profile = config.profile(Some("x86_64-unknown-linux-gnu"));
+		assert_eq!(profile.toolchain.as_deref(), Some("nightly-2019-07-14"));
+		assert_eq!(profile.wasm, Some(true));
+		assert_eq!(profile.debug, None);
+		assert_eq!(profile.wasm_toolchain, None);
+
+		let profile = config.profile(Some("x86_64-pc-windows-gnu"));
+		assert_eq!(profile.toolchain.as_deref(), Some("nightly-2019-07-14"));
+		assert_eq!(profile.wasm, Some(false));
+		assert_eq!(profile.debug, Some(true));
+		assert_eq!(profile.wasm_toolchain.as_deref(), Some("nightly-2019-08-01"));
+	}
+
+	#[test]
+	fn command_line_targets() {
+		let config = Config::parse(
+			r#"
+				universal = true
+				targets = ["x86_64-unknown-linux-gnu"]
+			"#,
+		)
+		.unwrap();
+
+		let (universal, targets) = config.targets(None, None);
+		assert!(universal);
+		assert_eq!(targets.len(), APPLE_TARGETS.len());
+
+		let (universal, targets) = config.targets(Some("aarch64-unknown-linux-gnu"), None);
+		assert!(!universal);
+		assert_eq!(targets, [Some(String::from("aarch64-unknown-linux-gnu"))]);
+
+		let (universal, targets) = config.targets(None, Some(false));
+		assert!(!universal);
+		assert_eq!(targets, [Some(String::from("x86_64-unknown-linux-gnu"))]);
+	}
+
+	#[test]
+	fn extend_registry() {
+		let config = Config::parse(
+			r#"
+				targets = ["mips-unknown-linux-gnu"]
+
+				[target.mips-unknown-linux-gnu]
+				linkers = ["mips-linux-gnu-gcc"]
+				envs = { ROCKSDB_LIB_DIR = "lib/rocksdb" }
+				pack = true
+
+				[target.x86_64-pc-windows-gnu]
+				linkers = ["x86_64-w64-mingw32-gcc-posix"]
+			"#,
+		)
+		.unwrap();
+
+		let spec = config.registry.get("mips-unknown-linux-gnu").unwrap();
+		assert_eq!(spec.linkers, ["mips-linux-gnu-gcc"]);
+		assert_eq!(
+			spec.envs,
+			[(String::from("ROCKSDB_LIB_DIR"), String::from("lib/rocksdb"))]
+		);
+		assert_eq!(config.profile(Some("mips-unknown-linux-gnu")).pack, Some(true));
+
+		// the rest of a built-in TARGET is kept
+		let spec = config.registry.get("x86_64-pc-windows-gnu").unwrap();
+		assert_eq!(spec.linkers, ["x86_64-w64-mingw32-gcc-posix"]);
+		assert!(spec.deps.is_some());
+	}
+
+	#[test]
+	fn reject_bad_keys() {
+		for (content, key) in [
+			("wsam = true", "wsam"),
+			("pack = \"yes\"", "pack"),
+			("cargo-config = \"home\"", "cargo-config"),
+			("targets = [\"mips-unknown-linux-gnu\"]", "targets"),
+			(
+				"[target.x86_64-pc-windows-gnu]\nlinker = \"gcc\"",
+				"target.x86_64-pc-windows-gnu.linker",
+			),
+		]
+		.iter()
+		{
+			assert_eq!(Config::parse(content).unwrap_err().0.as_deref(), Some(*key));
+		}
+	}
+}
@@ -0,0 +1,597 @@
+// --- std ---
+use std::{env, fmt, fs, process::Command};
+// --- external ---
+use colored::Colorize;
+// --- custom ---
+use crate::{
+	builder::Options,
+	cargo_config::{linker_env, CargoConfig},
+	env_var::{detect_linker, BUILD_DEPS, GET_SDK},
+	error::{Error, Stage},
+	host::OS,
+	message::{Event, Output},
+	package::PackageManager,
+	tool::{is_installed, RUSTUP_UNIX, RUSTUP_WINDOWS, WASM_GC},
+	util::run,
+};
+
+/// The columns of the readiness matrix
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Category {
+	Prerequisite,
+	Toolchain,
+	Linker,
+	Deps,
+	CargoConfig,
+	Env,
+}
+
+impl Category {
+	const ALL: [Category; 6] = [
+		Category::Prerequisite,
+		Category::Toolchain,
+		Category::Linker,
+		Category::Deps,
+		Category::CargoConfig,
+		Category::Env,
+	];
+
+	/// The stage which fails if the category is not ready
+	pub fn stage(self) -> Stage {
+		match self {
+			Category::Toolchain => Stage::Toolchain,
+			_ => Stage::Deps,
+		}
+	}
+}
+
+impl fmt::Display for Category {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			Category::Prerequisite => write!(f, "prerequisites"),
+			Category::Toolchain => write!(f, "toolchain"),
+			Category::Linker => write!(f, "linker"),
+			Category::Deps => write!(f, "deps"),
+			Category::CargoConfig => write!(f, "cargo config"),
+			Category::Env => write!(f, "env vars"),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct Check {
+	pub category: Category,
+	pub name: String,
+	/// The version/path found, empty if it is missing
+	pub found: String,
+	/// How to make it ready
+	pub fix: String,
+}
+
+impl Check {
+	pub fn is_ready(&self) -> bool {
+		!self.found.is_empty()
+	}
+}
+
+/// Everything needed to build a TARGET, checked without installing/changing anything
+#[derive(Debug)]
+pub struct Report {
+	pub target: String,
+	pub checks: Vec<Check>,
+}
+
+impl Report {
+	pub fn new(options: &Options) -> Self {
+		let mut report = Self {
+			target: options.run_target().to_owned(),
+			checks: vec![],
+		};
+		let manager = PackageManager::detect(&options.host_os);
+		let install = |prerequisite: &str, fallback: &str| {
+			manager
+				.and_then(|manager| manager.install_hint(prerequisite, &options.host))
+				.unwrap_or_else(|| fallback.to_owned())
+		};
+
+		// the README requirements
+		for (name, prerequisite) in [("cmake", "cmake"), ("git", "git"), ("llvm", "clang")] {
+			report.push(
+				Category::Prerequisite,
+				name,
+				first_line(run(Command::new(prerequisite).arg("--version"))),
+				install(prerequisite, "install it from your package manager"),
+			);
+		}
+		// the same as the install plan: openssl on Linux, the others if the TARGET `requires` them
+		let requires = |prerequisite: &str| {
+			options
+				.target_spec
+				.as_ref()
+				.is_some_and(|spec| spec.requires.iter().any(|required| required == prerequisite))
+		};
+		for (name, k, module, prerequisite, required) in [
+			(
+				"libopenssl",
+				"OPENSSL_LIB_DIR",
+				"openssl",
+				"openssl-dev",
+				matches!(options.host_os, OS::Linux(_)),
+			),
+			("librocksdb", "ROCKSDB_LIB_DIR", "rocksdb", "rocksdb-dev", requires("rocksdb-dev")),
+		] {
+			let found = if options
+				.target_spec
+				.as_ref()
+				.is_some_and(|spec| spec.envs.iter().any(|(k_, _)| k_ == k))
+			{
+				// the deps bundle ships it, checked with the env vars below
+				format!("`{}` of the deps", k)
+			} else if !required {
+				continue;
+			} else if let Ok(v) = env::var(k) {
+				v
+			} else {
+				library(&options.host_os, module)
+			};
+			report.push(
+				Category::Prerequisite,
+				name,
+				found,
+				install(
+					prerequisite,
+					&format!("install it from your package manager or set `{}`", k),
+				),
+			);
+		}
+
+		let rustup = first_line(run(Command::new("rustup").arg("--version")));
+		let toolchain_list = if rustup.is_empty() {
+			String::new()
+		} else {
+			run(Command::new("rustup").args(["toolchain", "list"])).unwrap_or_default()
+		};
+		let toolchain = format!("{}-{}", options.toolchain, options.host);
+		let wasm_toolchain = format!("{}-{}", options.wasm_toolchain(), options.host);
+		let toolchain_installed = is_installed(&toolchain_list, &toolchain);
+		let installed_targets = |toolchain: &str| {
+			if is_installed(&toolchain_list, toolchain) {
+				run(Command::new("rustup").args(["target", "list", "--installed", "--toolchain", toolchain]))
+					.unwrap_or_default()
+			} else {
+				String::new()
+			}
+		};
+		let native_targets = installed_targets(&toolchain);
+		let wasm_targets = installed_targets(&wasm_toolchain);
+		report.push(
+			Category::Toolchain,
+			"rustup",
+			rustup,
+			if cfg!(target_os = "windows") {
+				RUSTUP_WINDOWS
+			} else {
+				RUSTUP_UNIX
+			},
+		);
+		report.push(
+			Category::Toolchain,
+			"cargo",
+			first_line(run(Command::new("cargo").arg("--version"))),
+			"rustup toolchain install stable",
+		);
+		let mut toolchains = vec![("toolchain", &toolchain)];
+		// the wasm tools are needed with `options.wasm` only, like the builder
+		if options.wasm && wasm_toolchain != toolchain {
+			toolchains.push(("wasm toolchain", &wasm_toolchain));
+		}
+		for (name, toolchain) in toolchains {
+			report.push(
+				Category::Toolchain,
+				name,
+				if is_installed(&toolchain_list, toolchain) {
+					toolchain.to_owned()
+				} else {
+					String::new()
+				},
+				format!("rustup toolchain install {}", toolchain),
+			);
+		}
+		// the run target on the native toolchain, wasm32 on the wasm one
+		let mut targets = vec![("run target", options.run_target(), &toolchain, &native_targets)];
+		if options.wasm {
+			targets.push(("wasm target", "wasm32-unknown-unknown", &wasm_toolchain, &wasm_targets));
+		}
+		for (name, target, toolchain, installed_targets) in targets {
+			report.push(
+				Category::Toolchain,
+				name,
+				if installed_targets.lines().any(|line| line.trim() == target) {
+					target.to_owned()
+				} else {
+					String::new()
+				},
+				format!("rustup target add {} --toolchain {}", target, toolchain),
+			);
+		}
+		if let Some(rust_toolchain) = &options.rust_toolchain {
+			let installed_components = if toolchain_installed {
+				run(Command::new("rustup").args(["component", "list", "--installed", "--toolchain", &toolchain]))
+					.unwrap_or_default()
+			} else {
+				String::new()
+			};
+			let host_suffix = format!("-{}", options.host);
+
+			for target in rust_toolchain
+				.targets
+				.iter()
+				.filter(|target| *target != options.run_target() && *target != "wasm32-unknown-unknown")
+			{
+				report.push(
+					Category::Toolchain,
+					&format!("target {}", target),
+					if native_targets.lines().any(|line| line.trim() == target) {
+						target.to_owned()
+					} else {
+						String::new()
+					},
+					format!("rustup target add {} --toolchain {}", target, toolchain),
+				);
+			}
+			for component in &rust_toolchain.components {
+				report.push(
+					Category::Toolchain,
+					&format!("component {}", component),
+					if installed_components
+						.lines()
+						.any(|line| line.trim_end_matches(host_suffix.as_str()) == component)
+					{
+						component.to_owned()
+					} else {
+						String::new()
+					},
+					format!("rustup component add {} --toolchain {}", component, toolchain),
+				);
+			}
+		}
+		if options.wasm {
+			report.push(
+				Category::Toolchain,
+				"wasm-gc",
+				if run(Command::new("wasm-gc").arg("--help")).is_ok() {
+					String::from(WASM_GC)
+				} else {
+					String::new()
+				},
+				format!("cargo install --git {}", WASM_GC),
+			);
+		}
+
+		let spec = if let Some(spec) = &options.target_spec {
+			spec
+		} else {
+			return report;
+		};
+		let triple = spec.triple.as_str();
+		let deps_command = format!("darwinia-builder deps --target {}", triple);
+
+		let linkers = spec.linkers(&options.host);
+		let linker = linkers
+			.iter()
+			.find_map(|linker| detect_linker(linker).ok().flatten().map(|version| (*linker, version)));
+		let linker_fix = spec
+			.hint(&options.host_os, &options.host, &options.toolchain)
+			.or_else(|| {
+				let manager = manager?;
+				if let Some(packages) = spec.packages(manager) {
+					return Some(manager.install_line(packages, false));
+				}
+				let packages = spec
+					.requires
+					.iter()
+					.map(|prerequisite| manager.packages(prerequisite, &options.host))
+					.collect::<Option<Vec<_>>>()?
+					.concat();
+
+				Some(manager.install_line(&packages, false))
+			})
+			.unwrap_or_else(|| String::from("install it from your package manager"));
+		report.push(
+			Category::Linker,
+			&linkers.join(" or "),
+			linker
+				.as_ref()
+				.map(|(linker, version)| format!("{} {}", linker, first_line(Ok(version.to_owned()))))
+				.unwrap_or_default(),
+			linker_fix.clone(),
+		);
+		if let Some(cc) = &spec.cc {
+			report.push(
+				Category::Linker,
+				cc,
+				first_line(detect_linker(cc).map(Option::unwrap_or_default)),
+				linker_fix,
+			);
+		}
+
+		let mut dir = options.root.clone();
+		if let Some((folder, download_link)) = &spec.deps {
+			dir.push(folder);
+			report.push(
+				Category::Deps,
+				"deps",
+				if dir.is_dir() {
+					dir.to_string_lossy().to_string()
+				} else {
+					String::new()
+				},
+				match download_link {
+					Some(download_link) => format!("{} (or download {})", deps_command, download_link),
+					None => format!(
+						"build it into {} by hand, it is not released, see {}",
+						dir.to_string_lossy(),
+						BUILD_DEPS
+					),
+				},
+			);
+		}
+
+		let program = linker
+			.as_ref()
+			.map(|(linker, _)| *linker)
+			.or_else(|| linkers.first().copied())
+			.and_then(|linker| linker.split(' ').next())
+			.unwrap_or_default();
+		let fix = match options.cargo_config.path(&options.root) {
+			Some(path) => format!(
+				"{}, or add `[target.{}] linker = \"{}\"` to {}",
+				deps_command,
+				triple,
+				program,
+				path.to_string_lossy()
+			),
+			None => format!("install the linker, or set `{}`", linker_env(triple)),
+		};
+		report.push(
+			Category::CargoConfig,
+			"config file",
+			cargo_config(options, triple, linker.is_some()),
+			fix,
+		);
+
+		for (k, folder) in &spec.envs {
+			let found = env::var(k).unwrap_or_else(|_| {
+				let dir = dir.join(folder);
+				if dir.is_dir() {
+					dir.to_string_lossy().to_string()
+				} else {
+					String::new()
+				}
+			});
+			report.push(Category::Env, k, found, format!("{} or set `{}`", deps_command, k));
+		}
+		for (k, folders) in &spec.sdk_envs {
+			let found = env::var(k).unwrap_or_else(|_| match &options.sdk {
+				Some(sdk) if folders.iter().all(|folder| sdk.join(folder).is_dir()) => folders
+					.iter()
+					.map(|folder| sdk.join(folder).to_string_lossy().to_string())
+					.collect::<Vec<_>>()
+					.join(";"),
+				_ => String::new(),
+			});
+			report.push(Category::Env, k, found, GET_SDK);
+		}
+
+		report
+	}
+
+	pub fn is_ready(&self) -> bool {
+		self.checks.iter().all(Check::is_ready)
+	}
+
+	/// Each check of the TARGET, with the fix of the failing ones
+	pub fn print(&self, output: &Output) {
+		if output.is_json() {
+			output.emit(Event::Doctor { report: self });
+			return;
+		}
+
+		output.line(&format!("{} {}", "TARGET:".green(), self.target.cyan()));
+		for check in &self.checks {
+			if check.is_ready() {
+				output.found(&check.name, &check.found);
+			} else {
+				output.missing(&check.name, &check.fix);
+			}
+		}
+	}
+
+	/// Fail with the missing items, in the stage of the first one
+	pub fn check(&self) -> Result<(), Error> {
+		let missing = self.checks.iter().filter(|check| !check.is_ready()).collect::<Vec<_>>();
+		if let Some(first) = missing.first() {
+			Err(Error::Missing(missing.iter().map(|check| check.name.clone()).collect())
+				.in_stage(first.category.stage(), &self.target))
+		} else {
+			Ok(())
+		}
+	}
+
+	fn push(&mut self, category: Category, name: &str, found: String, fix: impl Into<String>) {
+		self.checks.push(Check {
+			category,
+			name: name.to_owned(),
+			found,
+			fix: fix.into(),
+		});
+	}
+}
+
+/// Check and report each TARGET, fail with the stage of the first one which is not ready
+pub fn report(options: &[Options]) -> Result<(), Error> {
+	let output = options.first().map(|options| options.output).unwrap_or_default();
+	let reports = options.iter().map(Report::new).collect::<Vec<_>>();
+	for report in &reports {
+		report.print(&output);
+	}
+	print_matrix(&reports, &output);
+
+	reports.iter().try_for_each(Report::check)
+}
+
+/// One row per TARGET, `-` if the category does not apply to it
+pub fn print_matrix(reports: &[Report], output: &Output) {
+	if output.is_json() {
+		return;
+	}
+
+	let width = reports
+		.iter()
+		.map(|report| report.target.len())
+		.chain(Some("TARGET".len()))
+		.max()
+		.unwrap_or_default();
+	let columns = Category::ALL.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+	println!();
+	print!("{:<width$}", "TARGET", width = width);
+	for column in &columns {
+		print!("  {}", column);
+	}
+	println!("  ready");
+
+	for report in reports {
+		print!("{:<width$}", report.target, width = width);
+		for (category, column) in Category::ALL.iter().zip(&columns) {
+			let mut checks = report
+				.checks
+				.iter()
+				.filter(|check| check.category == *category)
+				.peekable();
+			let cell = if checks.peek().is_none() {
+				format!("{:<width$}", "-", width = column.len()).normal()
+			} else if checks.all(Check::is_ready) {
+				format!("{:<width$}", "✓", width = column.len()).green()
+			} else {
+				format!("{:<width$}", "✗", width = column.len()).red()
+			};
+			print!("  {}", cell);
+		}
+		if report.is_ready() {
+			println!("  {}", "✓".green());
+		} else {
+			println!("  {}", "✗".red());
+		}
+	}
+}
+
+fn first_line(output: Result<String, Error>) -> String {
+	output
+		.map(|output| output.lines().next().unwrap_or_default().to_owned())
+		.unwrap_or_default()
+}
+
+/// The version/prefix of the system library, empty if it is not installed
+fn library(host_os: &OS, module: &str) -> String {
+	match host_os {
+		OS::Linux(_) => first_line(run(Command::new("pkg-config").args(["--modversion", module])))
+			.split_whitespace()
+			.next()
+			.map(|version| format!("{} {}", module, version))
+			.unwrap_or_default(),
+		OS::macOS => first_line(run(Command::new("brew").args(["--prefix", "--installed", module]))),
+		OS::Windows => String::new(),
+	}
+}
+
+/// The file (or env var) which sets the linker of the TARGET, empty if it is not set
+///
+/// With `--cargo-config=env` the builder passes the env var itself once the linker is found
+fn cargo_config(options: &Options, triple: &str, linker_found: bool) -> String {
+	let k = linker_env(triple);
+	if env::var_os(&k).is_some() || (options.cargo_config == CargoConfig::Env && linker_found) {
+		return k;
+	}
+
+	let project_dir = options.root.join(".cargo");
+	let global_dir = dirs::home_dir().unwrap_or_default().join(".cargo");
+	for path in [
+		project_dir.join("config"),
+		project_dir.join("config.toml"),
+		global_dir.join("config"),
+		global_dir.join("config.toml"),
+	] {
+		let set = fs::read_to_string(&path)
+			.ok()
+			.and_then(|config| config.parse::<toml::Value>().ok())
+			.is_some_and(|config| {
+				config
+					.get("target")
+					.and_then(|targets| targets.get(triple))
+					.and_then(|target| target.get("linker"))
+					.is_some()
+			});
+		if set {
+			return path.to_string_lossy().to_string();
+		}
+	}
+
+	String::new()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::Config;
+
+	#[test]
+	fn check_stage() {
+		let mut report = Report {
+			target: String::from("x86_64-unknown-linux-gnu"),
+			checks: vec![],
+		};
+		report.push(
+			Category::Prerequisite,
+			"cmake",
+			String::from("cmake version 3.25.1"),
+			"",
+		);
+		assert!(report.check().is_ok());
+
+		report.push(Category::Toolchain, "wasm-gc", String::new(), "");
+		report.push(Category::Env, "ROCKSDB_LIB_DIR", String::new(), "");
+		let e = report.check().unwrap_err();
+		assert_eq!(e.exit_code(), Stage::Toolchain.exit_code());
+		assert!(e.to_string().ends_with("missing wasm-gc, ROCKSDB_LIB_DIR"), "{}", e);
+	}
+
+	#[test]
+	fn wasm_and_rocksdb_by_options() {
+		let names = |wasm: bool| {
+			let mut options = Options::new(&Config::default(), Some(String::from("x86_64-unknown-linux-gnu"))).unwrap();
+			options.wasm = wasm;
+			options.wasm_toolchain = Some(String::from("nightly-2019-08-01"));
+
+			Report::new(&options)
+				.checks
+				.into_iter()
+				.map(|check| check.name)
+				.collect::<Vec<_>>()
+		};
+
+		let without_wasm = names(false);
+		for name in ["wasm toolchain", "wasm target", "wasm-gc"] {
+			assert!(!without_wasm.iter().any(|name_| name_ == name), "{:?}", without_wasm);
+		}
+		assert!(names(true).iter().any(|name| name == "wasm-gc"));
+		// the deps bundle ships it, it is not required from the system
+		assert!(without_wasm.iter().any(|name| name == "librocksdb"));
+		// nor from the system, unless the TARGET `requires` it
+		let mut options = Options::new(&Config::default(), Some(String::from("x86_64-unknown-linux-gnu"))).unwrap();
+		options.target_spec.as_mut().unwrap().envs.clear();
+		assert!(!Report::new(&options).checks.iter().any(|check| check.name == "librocksdb"));
+		options.target_spec.as_mut().unwrap().requires.push(String::from("rocksdb-dev"));
+		assert!(Report::new(&options).checks.iter().any(|check| check.name == "librocksdb"));
+	}
+}
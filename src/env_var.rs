@@ -0,0 +1,684 @@
+// --- std ---
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+	process::Command,
+};
+// --- custom ---
+use crate::{
+	builder::Options,
+	cargo_config::{self, linker_env, rustflags_env, CargoConfig, TargetConfig},
+	error::{Error, Stage},
+	message::Output,
+	util::{download, run},
+};
+
+/// How to build the deps bundle of a TARGET which is not released
+pub(crate) const BUILD_DEPS: &str = "https://github.com/AurevoirXavier/darwinia-builder#deps-bundle";
+/// How to get the Windows SDK/CRT of the MSVC TARGETs
+pub(crate) const GET_SDK: &str = "pass `--sdk` or set `sdk`, e.g. `xwin --accept-license splat --output ~/.xwin`";
+/// e.g. `sysroot/usr/lib/<triple>/pkgconfig`
+const PKG_CONFIG_DEPTH: usize = 5;
+
+#[derive(Debug, Default)]
+pub struct EnvVar {
+	/// How cargo gets the linker, `CARGO_TARGET_<TRIPLE>_LINKER=<linker>` or `[target.<triple>] linker = "<linker>"`
+	/// of the cargo config file, empty if it is not set
+	pub linker_config: String,
+	/// The linker program passed to cargo
+	pub linker: String,
+	pub target_cc: String,
+	/// The C++ compiler next to `target_cc`, empty if it is not installed
+	pub target_cxx: String,
+	pub target_ar: String,
+	pub deps: String,
+	/// The required env vars of the TARGET, empty if not found
+	pub envs: Vec<(String, String)>,
+}
+
+impl EnvVar {
+	pub fn new(options: &Options) -> Result<Self, Error> {
+		let linker_config = String::new();
+		let mut linker_ = String::new();
+		let mut target_cc = String::new();
+		let mut target_cxx = String::new();
+		let mut target_ar = String::new();
+		let mut deps = String::new();
+		let mut envs = vec![];
+
+		let spec = if let Some(spec) = &options.target_spec {
+			spec
+		} else {
+			return Ok(Self {
+				linker_config,
+				linker: linker_,
+				target_cc,
+				target_cxx,
+				target_ar,
+				deps,
+				envs,
+			});
+		};
+		let target = spec.triple.as_str();
+
+		let linkers = spec.linkers(&options.host);
+		let mut found = None;
+		for linker in &linkers {
+			if let Some(version) = detect_linker(linker)? {
+				found = Some((*linker, version));
+				break;
+			}
+		}
+
+		if let Some((linker, version)) = found {
+			target_cc = linker.to_owned();
+			// cargo takes the program only, rustc passes e.g. `-m32` of the i686 TARGETs to the linker by itself
+			linker_ = linker.split(' ').next().unwrap_or_default().to_owned();
+
+			options.output.tool(target, linker, Ok(version.split('\n').next().unwrap_or_default()));
+
+			// e.g. `clang-cl` compiles the C deps while `lld-link` links
+			if let Some(cc) = &spec.cc {
+				if let Some(version) = detect_linker(cc)? {
+					target_cc = cc.to_owned();
+					options.output.tool(target, cc, Ok(version.split('\n').next().unwrap_or_default()));
+				} else {
+					target_cc.clear();
+					options.output.tool(target, cc, Err(""));
+				}
+			}
+			if let Some(cxx) = cxx(&target_cc) {
+				if let Some(version) = detect_linker(&cxx)? {
+					options.output.tool(target, &cxx, Ok(version.split('\n').next().unwrap_or_default()));
+					target_cxx = cxx;
+				} else {
+					options.output.tool(target, &cxx, Err("needed by the C++ deps e.g. librocksdb-sys"));
+				}
+			}
+			if let Some(ar) = &spec.ar {
+				target_ar = ar.to_owned();
+			} else if let Some(ar) = ar(linker) {
+				// the build scripts fall back to the HOST `ar` otherwise
+				if run(Command::new(&ar).arg("--version")).is_ok() {
+					target_ar = ar;
+				}
+			}
+		} else {
+			let hint = spec
+				.hint(&options.host_os, &options.host, &options.toolchain)
+				.unwrap_or_else(|| String::from("install it from your package manager"));
+			options.output.tool(target, &linkers.join(" or "), Err(&hint));
+		}
+
+		let mut dir = options.root.clone();
+		if let Some((folder, download_link)) = &spec.deps {
+			dir.push(folder);
+			check_deps(options, target, dir.as_path(), &mut deps, download_link.as_deref())?;
+		}
+
+		for (k, folder) in &spec.envs {
+			let mut v = String::new();
+			check_envs(&options.output, target, k, &mut v, dir.as_path(), folder);
+			envs.push((k.to_owned(), v));
+		}
+		envs.extend(sdk_envs(options, target, &spec.sdk_envs));
+
+		let mut env_var = Self {
+			linker_config,
+			linker: linker_,
+			target_cc,
+			target_cxx,
+			target_ar,
+			deps,
+			envs,
+		};
+		// the rustflags need the SYSROOT
+		if !env_var.linker.is_empty() {
+			env_var.linker_config = match options.cargo_config.path(&options.root) {
+				Some(path) => {
+					cargo_config::set_target(
+						target,
+						&env_var.target_config(options),
+						&path,
+						options.install,
+						&options.output,
+					)?
+				}
+				None => {
+					let linker_config = format!("{}={}", linker_env(target), env_var.linker);
+					options.output.env(target, "config file", Ok(&linker_config));
+
+					linker_config
+				}
+			};
+		}
+
+		Ok(env_var)
+	}
+
+	/// Fail with the missing linker/deps/env vars of the TARGET, if any
+	pub fn check(&self, options: &Options) -> Result<(), Error> {
+		let spec = match &options.target_spec {
+			Some(spec) => spec,
+			None => return Ok(()),
+		};
+		let mut items = vec![("config file", &self.linker_config), ("TARGET_CC", &self.target_cc)];
+		if spec.deps.is_some() {
+			items.push(("deps", &self.deps));
+		}
+		items.extend(self.envs.iter().map(|(k, v)| (k.as_str(), v)));
+
+		let missing = items
+			.iter()
+			.filter(|(_, v)| v.is_empty())
+			.map(|(k, _)| (*k).to_owned())
+			.collect::<Vec<_>>();
+		if missing.is_empty() {
+			Ok(())
+		} else {
+			Err(Error::Missing(missing).in_stage(Stage::Deps, options.run_target()))
+		}
+	}
+
+	/// The env vars to reuse the cross build by hand e.g. `cargo test --target <TARGET>`, see `env`
+	///
+	/// The rustc flags are passed to `cargo rustc` by the builder, so they are exported as `CARGO_TARGET_<TRIPLE>_RUSTFLAGS`
+	pub fn exports(&self, options: &Options) -> Vec<(String, String)> {
+		let mut exports = self.cross_envs(options);
+		let rustflags = self.rustflags(options);
+		if let (Some(target), CargoConfig::Env, false) = (&options.target, options.cargo_config, rustflags.is_empty()) {
+			exports.push((rustflags_env(target), rustflags.join(" ")));
+		}
+
+		exports
+	}
+
+	/// The env vars of the cross build for cargo and the build scripts (`cc`, `bindgen`, `pkg-config`),
+	/// empty if it is not a cross build
+	pub fn cross_envs(&self, options: &Options) -> Vec<(String, String)> {
+		let triple = match &options.target {
+			Some(target) => target.as_str(),
+			None => return vec![],
+		};
+		let mut envs = vec![];
+		let mut push = |k: String, v: &str| envs.push((k, v.to_owned()));
+
+		if options.cargo_config == CargoConfig::Env && !self.linker.is_empty() {
+			push(linker_env(triple), &self.linker);
+		}
+		push(String::from("TARGET_CC"), &self.target_cc);
+		for (k, v) in [
+			("CC", &self.target_cc),
+			("CXX", &self.target_cxx),
+			("AR", &self.target_ar),
+		] {
+			if !v.is_empty() {
+				if k != "CC" {
+					push(format!("TARGET_{}", k), v);
+				}
+				push(target_env(k, triple), v);
+			}
+		}
+
+		let sysroot = self.get("SYSROOT");
+		let mut clang_args = format!("--target={}", triple);
+		if let Some(sysroot) = sysroot {
+			let flag = format!("--sysroot={}", sysroot);
+			push(target_env("CFLAGS", triple), &flag);
+			push(target_env("CXXFLAGS", triple), &flag);
+			clang_args.push(' ');
+			clang_args.push_str(&flag);
+		}
+		push(target_env("BINDGEN_EXTRA_CLANG_ARGS", triple), &clang_args);
+
+		// the `.pc` files of the deps bundle, instead of the HOST's
+		if !self.deps.is_empty() {
+			let dirs = pkg_config_dirs(Path::new(&self.deps), PKG_CONFIG_DEPTH);
+			if let (false, Ok(path)) = (dirs.is_empty(), env::join_paths(&dirs)) {
+				push(target_env("PKG_CONFIG_PATH", triple), &path.to_string_lossy());
+				push(
+					target_env("PKG_CONFIG_SYSROOT_DIR", triple),
+					sysroot.unwrap_or(&self.deps),
+				);
+			}
+		}
+
+		// `SYSROOT` is passed to the linker, not to the build scripts
+		for (k, v) in &self.envs {
+			if k != "SYSROOT" {
+				push(k.to_owned(), v);
+			}
+		}
+		if options.is_static() {
+			push(String::from("OPENSSL_STATIC"), "1");
+			push(String::from("ROCKSDB_STATIC"), "1");
+		}
+
+		envs
+	}
+
+	/// The keys of `[target.<triple>]` in the cargo config file
+	pub fn target_config(&self, options: &Options) -> TargetConfig {
+		TargetConfig {
+			linker: self.linker.clone(),
+			ar: Some(self.target_ar.clone()).filter(|ar| !ar.is_empty()),
+			rustflags: self.rustflags(options),
+		}
+	}
+
+	/// The rustc flags of the TARGET, the SYSROOT of the linker and the static CRT
+	pub fn rustflags(&self, options: &Options) -> Vec<String> {
+		let mut rustflags = vec![];
+		if let Some(sysroot) = self.get("SYSROOT") {
+			rustflags.push(String::from("-C"));
+			rustflags.push(format!("link_args=--sysroot={}", sysroot));
+		}
+		if options.is_static() {
+			rustflags.push(String::from("-C"));
+			rustflags.push(String::from("target-feature=+crt-static"));
+		}
+
+		rustflags
+	}
+
+	/// The value of the required env var, `None` if it is not required or not found
+	pub fn get(&self, k: &str) -> Option<&str> {
+		self.envs
+			.iter()
+			.find(|(k_, v)| k_ == k && !v.is_empty())
+			.map(|(_, v)| v.as_str())
+	}
+}
+
+/// The target specific env var of the build scripts e.g. `CC_aarch64_unknown_linux_gnu`
+fn target_env(k: &str, triple: &str) -> String {
+	format!("{}_{}", k, triple.replace(['-', '.'], "_"))
+}
+
+/// The C++ compiler of the C one (with its flags) e.g. `x86_64-w64-mingw32-gcc-posix` to `x86_64-w64-mingw32-g++-posix`
+fn cxx(cc: &str) -> Option<String> {
+	let (program, flags) = cc.split_once(' ').unwrap_or((cc, ""));
+	let (name, replacement) = if program.contains("clang-cl") {
+		return Some(cc.to_owned());
+	} else if program.contains("gcc") {
+		("gcc", "g++")
+	} else if program.contains("clang") {
+		("clang", "clang++")
+	} else if program == "cc" {
+		("cc", "c++")
+	} else {
+		return None;
+	};
+	let i = program.rfind(name)?;
+	let program = format!("{}{}{}", &program[..i], replacement, &program[i + name.len()..]);
+
+	Some(if flags.is_empty() {
+		program
+	} else {
+		format!("{} {}", program, flags)
+	})
+}
+
+/// The `ar` of the cross linker e.g. `aarch64-linux-gnu-gcc` to `aarch64-linux-gnu-ar`
+///
+/// `None` for a wrapper without a triple e.g. `musl-gcc`, there is no `musl-ar`
+fn ar(linker: &str) -> Option<String> {
+	let program = linker.split(' ').next().unwrap_or_default();
+	if let Some(i) = program.rfind("-gcc") {
+		let prefix = &program[..i];
+
+		prefix.contains('-').then(|| format!("{}-ar", prefix))
+	} else if program.contains("clang") {
+		Some(String::from("llvm-ar"))
+	} else {
+		None
+	}
+}
+
+/// Look for the `pkgconfig` folders of the deps bundle, at most `depth` levels deep
+fn pkg_config_dirs(dir: &Path, depth: usize) -> Vec<PathBuf> {
+	let mut dirs = vec![];
+	if let Ok(entries) = fs::read_dir(dir) {
+		for entry in entries.filter_map(Result::ok) {
+			let path = entry.path();
+			if !path.is_dir() {
+				continue;
+			}
+
+			if entry.file_name() == "pkgconfig" {
+				dirs.push(path);
+			} else if depth > 1 {
+				dirs.extend(pkg_config_dirs(&path, depth - 1));
+			}
+		}
+	}
+	dirs.sort();
+
+	dirs
+}
+
+/// Return the version of the linker, `None` if it is not installed
+///
+/// The linker might come with some flags e.g. `gcc -m32`,
+/// in that case check the multilib is installed as well
+pub(crate) fn detect_linker(linker: &str) -> Result<Option<String>, Error> {
+	let mut args = linker.split(' ');
+	let program = args.next().unwrap_or_default();
+	let args = args.collect::<Vec<_>>();
+
+	let version = match run(Command::new(program).args(&args).arg("--version")) {
+		Ok(version) => version,
+		Err(e) if e.is_not_found() => return Ok(None),
+		Err(e) => return Err(e),
+	};
+	if !args.is_empty() {
+		// prints the bare file name if the libc of the flags is not installed
+		let libc = run(Command::new(program).args(&args).arg("-print-file-name=libc.so"))?;
+		if !Path::new(&libc).is_file() {
+			return Ok(None);
+		}
+	}
+
+	Ok(Some(version))
+}
+
+/// Download the deps with `options.install` (unless `options.offline`) if they are not in `dir`
+fn check_deps(
+	options: &Options,
+	target: &str,
+	dir: &Path,
+	deps: &mut String,
+	download_link: Option<&str>,
+) -> Result<(), Error> {
+	let root = options.root.as_path();
+	if !dir.exists() {
+		let download_link = match download_link {
+			Some(download_link) if options.install => download_link,
+			Some(download_link) => {
+				options.output.env(target, "deps", Err(download_link));
+				return Ok(());
+			}
+			None => {
+				let hint = format!(
+					"not released, build the bundle into {} by hand, see {}",
+					dir.to_string_lossy(),
+					BUILD_DEPS
+				);
+				if options.install {
+					return Err(Error::Unavailable(format!("the deps of {} are {}", target, hint)));
+				}
+				options.output.env(target, "deps", Err(&hint));
+
+				return Ok(());
+			}
+		};
+		if options.offline {
+			return Err(Error::Unavailable(format!(
+				"the deps are not found at {} and `--offline` forbids downloading them, download {} on a connected machine and extract it into {}",
+				dir.to_string_lossy(),
+				download_link,
+				root.to_string_lossy()
+			)));
+		}
+
+		options.output.missing("deps", &format!("automatically download from: {}", download_link));
+
+		let file = download(download_link, root, &options.output)?;
+		run(Command::new("tar").current_dir(root).arg("xf").arg(&file))?;
+	}
+	*deps = dir.to_string_lossy().to_string();
+	options.output.env(target, "deps", Ok(deps));
+
+	Ok(())
+}
+
+/// The SDK env vars of the TARGET, empty without `options.sdk`
+fn sdk_envs(options: &Options, target: &str, sdk_envs: &[(String, Vec<String>)]) -> Vec<(String, String)> {
+	if sdk_envs.is_empty() {
+		return vec![];
+	}
+
+	if let Some(sdk) = &options.sdk {
+		sdk_envs
+			.iter()
+			.map(|(k, folders)| (k.to_owned(), check_sdk_envs(&options.output, target, k, sdk, folders)))
+			.collect()
+	} else {
+		options.output.env(target, "SDK", Err(GET_SDK));
+
+		sdk_envs.iter().map(|(k, _)| (k.to_owned(), String::new())).collect()
+	}
+}
+
+/// Join the SDK folders with `;` like on Windows, empty if any of them is not found
+fn check_sdk_envs(output: &Output, target: &str, k: &str, sdk: &Path, folders: &[String]) -> String {
+	if let Ok(v) = env::var(k) {
+		output.env(target, k, Ok(&v));
+		return v;
+	}
+
+	let dirs = folders.iter().map(|folder| sdk.join(folder)).collect::<Vec<_>>();
+	if let Some(dir) = dirs.iter().find(|dir| !dir.is_dir()) {
+		output.env(target, k, Err(&dir.to_string_lossy()));
+
+		return String::new();
+	}
+
+	let v = dirs
+		.iter()
+		.map(|dir| dir.to_string_lossy())
+		.collect::<Vec<_>>()
+		.join(";");
+	output.env(target, k, Ok(&v));
+
+	v
+}
+
+fn check_envs(output: &Output, target: &str, k: &str, v: &mut String, dir: &Path, folder: &str) {
+	if let Ok(v_) = env::var(k) {
+		*v = v_;
+		output.env(target, k, Ok(v));
+	} else {
+		let mut dir = dir.to_path_buf();
+		dir.push(folder);
+		if dir.as_path().is_dir() {
+			*v = dir.to_string_lossy().to_string();
+
+			output.env(target, k, Ok(v));
+		} else {
+			output.env(target, k, Err(""));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{config::Config, doctor::Report, host::OS, util::TempDir};
+
+	const MSVC: &str = "x86_64-pc-windows-msvc";
+	const AARCH64: &str = "aarch64-unknown-linux-gnu";
+
+	/// The `xwin splat` layout in a temporary dir, with the SDK env vars of the test
+	fn sdk_dir(test: &str) -> (TempDir, Vec<(String, Vec<String>)>) {
+		let dir = TempDir::new(test);
+		for folder in ["xwin/crt/include", "xwin/sdk/include/ucrt"] {
+			fs::create_dir_all(dir.join(folder)).unwrap();
+		}
+		let sdk_envs = vec![(
+			format!("DARWINIA_BUILDER_TEST_INCLUDE_{}", test.to_uppercase().replace('-', "_")),
+			vec![String::from("crt/include"), String::from("sdk/include/ucrt")],
+		)];
+
+		(dir, sdk_envs)
+	}
+
+	#[test]
+	fn aarch64_cross_envs() {
+		let deps = TempDir::new("aarch64-cross-envs");
+		let sysroot = deps.join("sysroot");
+		let pkg_config = sysroot.join("usr/lib/aarch64-linux-gnu/pkgconfig");
+		fs::create_dir_all(&pkg_config).unwrap();
+		let path = |path: &Path| path.to_string_lossy().to_string();
+		let mut options = Options::new(&Config::default(), Some(String::from(AARCH64))).unwrap();
+		options.cargo_config = CargoConfig::Env;
+		assert_eq!(
+			options.target_spec.as_ref().unwrap().linkers("x86_64-unknown-linux-gnu"),
+			["aarch64-linux-gnu-gcc", "aarch64-unknown-linux-gnu-gcc"]
+		);
+		let env_var = EnvVar {
+			linker_config: String::new(),
+			linker: String::from("aarch64-linux-gnu-gcc"),
+			target_cc: String::from("aarch64-linux-gnu-gcc"),
+			target_cxx: String::from("aarch64-linux-gnu-g++"),
+			target_ar: String::from("aarch64-linux-gnu-ar"),
+			deps: path(&deps),
+			envs: vec![
+				(String::from("SYSROOT"), path(&sysroot)),
+				(String::from("ROCKSDB_LIB_DIR"), path(&deps.join("lib/rocksdb"))),
+			],
+		};
+
+		let envs = env_var.cross_envs(&options);
+		let get = |k: &str| envs.iter().find(|(k_, _)| k_ == k).map(|(_, v)| v.as_str());
+		assert_eq!(
+			get("CARGO_TARGET_AARCH64_UNKNOWN_LINUX_GNU_LINKER"),
+			Some("aarch64-linux-gnu-gcc")
+		);
+		assert_eq!(get("CXX_aarch64_unknown_linux_gnu"), Some("aarch64-linux-gnu-g++"));
+		assert_eq!(get("AR_aarch64_unknown_linux_gnu"), Some("aarch64-linux-gnu-ar"));
+		assert_eq!(
+			get("CFLAGS_aarch64_unknown_linux_gnu"),
+			Some(&*format!("--sysroot={}", path(&sysroot)))
+		);
+		assert_eq!(get("PKG_CONFIG_PATH_aarch64_unknown_linux_gnu"), Some(&*path(&pkg_config)));
+		assert_eq!(get("PKG_CONFIG_SYSROOT_DIR_aarch64_unknown_linux_gnu"), Some(&*path(&sysroot)));
+		assert_eq!(get("ROCKSDB_LIB_DIR"), Some(&*path(&deps.join("lib/rocksdb"))));
+		// passed to the linker only
+		assert_eq!(get("SYSROOT"), None);
+		assert_eq!(
+			env_var.rustflags(&options),
+			["-C", &*format!("link_args=--sysroot={}", path(&sysroot))]
+		);
+	}
+
+	#[test]
+	fn sdk_of_config_file() {
+		let (dir, folders) = sdk_dir("sdk-of-config-file");
+		let path = dir.join("darwinia-builder.toml");
+		fs::write(&path, "[target.x86_64-pc-windows-msvc]\nsdk = \"xwin\"\n").unwrap();
+		let options = Options::new(&Config::load(&path).unwrap(), Some(String::from(MSVC))).unwrap();
+
+		// relative to the config file, not to where the builder runs
+		assert_eq!(options.sdk.as_deref(), Some(dir.join("xwin").as_path()));
+		let sdk = dir.join("xwin");
+		assert_eq!(
+			sdk_envs(&options, MSVC, &folders),
+			[(
+				folders[0].0.clone(),
+				format!(
+					"{};{}",
+					sdk.join("crt/include").to_string_lossy(),
+					sdk.join("sdk/include/ucrt").to_string_lossy()
+				)
+			)]
+		);
+	}
+
+	#[test]
+	fn sdk_of_command_line() {
+		let (dir, mut folders) = sdk_dir("sdk-of-command-line");
+		let mut options = Options::new(&Config::default(), Some(String::from(MSVC))).unwrap();
+		options.sdk = Some(dir.join("xwin"));
+
+		let envs = sdk_envs(&options, MSVC, &folders);
+		assert!(envs[0].1.ends_with(&*dir.join("xwin/sdk/include/ucrt").to_string_lossy()), "{:?}", envs);
+		// empty if any folder is missing
+		folders[0].1.push(String::from("sdk/lib/um/x86_64"));
+		assert_eq!(sdk_envs(&options, MSVC, &folders), [(folders[0].0.clone(), String::new())]);
+	}
+
+	#[test]
+	fn sdk_missing() {
+		let (_dir, folders) = sdk_dir("sdk-missing");
+		let mut options = Options::new(&Config::default(), Some(String::from(MSVC))).unwrap();
+		options.sdk = None;
+		assert_eq!(sdk_envs(&options, MSVC, &folders), [(folders[0].0.clone(), String::new())]);
+
+		// doctor gives the same hint
+		options.target_spec.as_mut().unwrap().sdk_envs = folders.clone();
+		let report = Report::new(&options);
+		let check = report.checks.iter().find(|check| check.name == folders[0].0).unwrap();
+		assert!(!check.is_ready());
+		assert_eq!(check.fix, GET_SDK);
+	}
+
+	#[test]
+	fn report_without_install() {
+		// e.g. the CI mode
+		let mut options = Options::new(&Config::default(), Some(String::from(AARCH64))).unwrap();
+		let dir = TempDir::new("report-without-install");
+		options.root = dir.to_path_buf();
+		options.install = false;
+		let spec = options.target_spec.as_mut().unwrap();
+		let download_link = String::from("https://example.com/linux-aarch64.tar.gz");
+		spec.deps = Some((String::from("linux-aarch64"), Some(download_link)));
+
+		let env_var = EnvVar::new(&options).unwrap();
+		assert!(env_var.deps.is_empty());
+		// nothing is downloaded
+		assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+		let e = env_var.check(&options).unwrap_err();
+		assert_eq!(e.exit_code(), Stage::Deps.exit_code());
+		assert!(e.to_string().contains("deps"), "{}", e);
+	}
+
+	#[test]
+	fn missing_linker_on_windows() {
+		let mut options = Options::new(&Config::default(), Some(String::from(MSVC))).unwrap();
+		options.host_os = OS::Windows;
+		options.host = String::from("x86_64-pc-windows-gnu");
+		options.install = false;
+		let spec = options.target_spec.as_mut().unwrap();
+		spec.native_linker = None;
+		spec.linkers = vec![String::from("darwinia-builder-missing-linker")];
+		spec.cc = None;
+		spec.deps = None;
+		spec.envs.clear();
+		spec.sdk_envs.clear();
+		spec.hints.insert(String::from("windows"), String::from("install the missing linker"));
+
+		// reported as missing with the hint, like the other HOSTs
+		let env_var = EnvVar::new(&options).unwrap();
+		assert!(env_var.linker.is_empty());
+		assert!(env_var.check(&options).unwrap_err().to_string().contains("TARGET_CC"));
+		assert_eq!(
+			options.target_spec.as_ref().unwrap().hint(&options.host_os, &options.host, &options.toolchain),
+			Some(String::from("install the missing linker"))
+		);
+	}
+
+	#[test]
+	fn cross_tools() {
+		assert_eq!(
+			cxx("x86_64-w64-mingw32-gcc-posix").unwrap(),
+			"x86_64-w64-mingw32-g++-posix"
+		);
+		assert_eq!(cxx("gcc -m32").unwrap(), "g++ -m32");
+		assert_eq!(
+			cxx("x86_64-apple-darwin20.4-clang").unwrap(),
+			"x86_64-apple-darwin20.4-clang++"
+		);
+		assert_eq!(cxx("clang-cl").unwrap(), "clang-cl");
+		assert_eq!(cxx("lld-link"), None);
+
+		assert_eq!(ar("aarch64-linux-gnu-gcc").unwrap(), "aarch64-linux-gnu-ar");
+		assert_eq!(ar("x86_64-linux-musl-gcc").unwrap(), "x86_64-linux-musl-ar");
+		assert_eq!(ar("musl-gcc"), None);
+		assert_eq!(ar("gcc -m32"), None);
+		assert_eq!(
+			target_env("CC", "armv7-unknown-linux-gnueabihf"),
+			"CC_armv7_unknown_linux_gnueabihf"
+		);
+	}
+}
@@ -0,0 +1,299 @@
+// --- std ---
+use std::{
+	fmt, io,
+	path::{Path, PathBuf},
+	process::{Command, ExitStatus},
+};
+// --- custom ---
+use crate::config::ConfigError;
+
+#[derive(Debug)]
+pub enum Error {
+	Config(ConfigError),
+	UnsupportedHost(String),
+	UnsupportedTarget {
+		target: String,
+		host: String,
+	},
+	/// The command could not be spawned, e.g. not installed
+	Spawn {
+		command: String,
+		source: io::Error,
+	},
+	/// The command exited with a failure status
+	Command {
+		command: String,
+		status: ExitStatus,
+		stderr: String,
+	},
+	Io {
+		path: Option<PathBuf>,
+		source: io::Error,
+	},
+	Manifest {
+		path: PathBuf,
+		reason: String,
+	},
+	Download {
+		url: String,
+		reason: String,
+	},
+	/// The static binary still needs some shared libraries
+	Dynamic {
+		path: PathBuf,
+		libraries: Vec<String>,
+	},
+	/// Required tools/env vars which are not found by `Builder::check`
+	Missing(Vec<String>),
+	/// The command line is not enough for the subcommand, e.g. no TARGET
+	Usage(String),
+	/// The dates given to `bisect-toolchain` don't bound a regression
+	Bisect(String),
+	/// Something must be downloaded, but `--offline` forbids it or there is nothing to download it from
+	Unavailable(String),
+	/// The stage and the target which the error happened at
+	Stage {
+		stage: Stage,
+		target: String,
+		source: Box<Error>,
+	},
+}
+
+impl Error {
+	pub fn spawn(command: &Command, source: io::Error) -> Self {
+		Error::Spawn {
+			command: format!("{:?}", command),
+			source,
+		}
+	}
+
+	pub fn command(command: &Command, status: ExitStatus, stderr: &[u8]) -> Self {
+		Error::Command {
+			command: format!("{:?}", command),
+			status,
+			stderr: String::from_utf8_lossy(stderr).trim().to_owned(),
+		}
+	}
+
+	pub fn in_stage(self, stage: Stage, target: &str) -> Self {
+		Error::Stage {
+			stage,
+			target: target.to_owned(),
+			source: Box::new(self),
+		}
+	}
+
+	/// The command is not installed
+	pub fn is_not_found(&self) -> bool {
+		match self {
+			Error::Spawn { source, .. } => source.kind() == io::ErrorKind::NotFound,
+			_ => false,
+		}
+	}
+
+	/// The stage which the error happened at, if any
+	pub fn stage(&self) -> Option<Stage> {
+		match self {
+			Error::Stage { stage, .. } => Some(*stage),
+			_ => None,
+		}
+	}
+
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			Error::Stage { stage, .. } => stage.exit_code(),
+			_ => 1,
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			Error::Config(e) => write!(f, "config: {}", e),
+			Error::UnsupportedHost(host) => write!(f, "unsupported HOST: {}", host),
+			Error::UnsupportedTarget { target, host } => {
+				write!(f, "unsupported TARGET: {} on HOST: {}", target, host)
+			}
+			Error::Spawn { command, source } => write!(f, "failed to run {}: {}", command, source),
+			Error::Command {
+				command,
+				status,
+				stderr,
+			} => {
+				if stderr.is_empty() {
+					write!(f, "{} {}", command, status)
+				} else {
+					write!(f, "{} {}\n{}", command, status, stderr)
+				}
+			}
+			Error::Io {
+				path: Some(path),
+				source,
+			} => write!(f, "{}: {}", path.to_string_lossy(), source),
+			Error::Io { path: None, source } => write!(f, "{}", source),
+			Error::Manifest { path, reason } => write!(f, "{}: {}", path.to_string_lossy(), reason),
+			Error::Download { url, reason } => write!(f, "download {} failed: {}", url, reason),
+			Error::Dynamic { path, libraries } => write!(
+				f,
+				"{}: expect a static binary, but it needs {}",
+				path.to_string_lossy(),
+				libraries.join(", ")
+			),
+			Error::Missing(items) => write!(f, "missing {}", items.join(", ")),
+			Error::Usage(reason) => write!(f, "{}", reason),
+			Error::Bisect(reason) => write!(f, "bisect: {}", reason),
+			Error::Unavailable(reason) => write!(f, "{}", reason),
+			Error::Stage { stage, target, source } => write!(f, "{} failed ({}): {}", stage, target, source),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<ConfigError> for Error {
+	fn from(e: ConfigError) -> Self {
+		Error::Config(e)
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Error::Io { path: None, source: e }
+	}
+}
+
+/// Attach the path to an `io::Error`
+pub trait WithPath<T> {
+	fn with_path<P: AsRef<Path>>(self, path: P) -> Result<T, Error>;
+}
+
+impl<T> WithPath<T> for io::Result<T> {
+	fn with_path<P: AsRef<Path>>(self, path: P) -> Result<T, Error> {
+		self.map_err(|e| Error::Io {
+			path: Some(path.as_ref().to_path_buf()),
+			source: e,
+		})
+	}
+}
+
+/// The stages of the pipeline, each kind of failure exits with its own code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+	Toolchain,
+	Deps,
+	Wasm,
+	Build,
+	Pack,
+}
+
+impl Stage {
+	pub fn exit_code(self) -> i32 {
+		match self {
+			Stage::Toolchain => 2,
+			Stage::Deps => 3,
+			Stage::Wasm => 4,
+			Stage::Build => 5,
+			Stage::Pack => 6,
+		}
+	}
+}
+
+impl fmt::Display for Stage {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			Stage::Toolchain => write!(f, "toolchain"),
+			Stage::Deps => write!(f, "deps"),
+			Stage::Wasm => write!(f, "wasm build"),
+			Stage::Build => write!(f, "native build"),
+			Stage::Pack => write!(f, "pack"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	// --- std ---
+	use std::fs;
+	// --- custom ---
+	use super::*;
+	use crate::{
+		builder::package_name,
+		util::{run, TempDir},
+	};
+
+	#[test]
+	fn stage_messages() {
+		for (stage, exit_code, message) in [
+			(Stage::Toolchain, 2, "toolchain failed"),
+			(Stage::Deps, 3, "deps failed"),
+			(Stage::Wasm, 4, "wasm build failed"),
+			(Stage::Build, 5, "native build failed"),
+			(Stage::Pack, 6, "pack failed"),
+		] {
+			let e = Error::Missing(vec![String::from("wasm-gc")]).in_stage(stage, "x86_64-unknown-linux-gnu");
+			assert_eq!(e.stage(), Some(stage));
+			assert_eq!(e.exit_code(), exit_code);
+			assert_eq!(
+				e.to_string(),
+				format!("{} (x86_64-unknown-linux-gnu): missing wasm-gc", message)
+			);
+		}
+
+		let e = Error::Usage(String::from("pack requires a TARGET"));
+		assert_eq!(e.stage(), None);
+		assert_eq!(e.exit_code(), 1);
+	}
+
+	#[test]
+	fn failed_command() {
+		// the exit status fails it, not only the spawning
+		let e = run(Command::new("sh").args(["-c", "echo built; echo broken >&2; exit 3"])).unwrap_err();
+		match &e {
+			Error::Command { status, stderr, .. } => {
+				assert_eq!(status.code(), Some(3));
+				assert_eq!(stderr, "broken");
+			}
+			_ => panic!("{}", e),
+		}
+		assert!(!e.is_not_found());
+		assert!(e.to_string().ends_with("\nbroken"), "{}", e);
+
+		assert!(run(&mut Command::new("darwinia-builder-not-installed")).unwrap_err().is_not_found());
+		assert_eq!(run(Command::new("sh").args(["-c", "echo built"])).unwrap(), "built");
+	}
+
+	#[test]
+	fn error_context() {
+		let dir = TempDir::new("error-context");
+		let path = dir.join("Cargo.toml");
+
+		let e = fs::read_to_string(&path).with_path(&path).unwrap_err();
+		assert!(e.to_string().starts_with(&*path.to_string_lossy()), "{}", e);
+		let e = run(Command::new("darwinia-builder-not-installed").arg("--version")).unwrap_err();
+		assert!(
+			e.to_string().starts_with("failed to run ") && e.to_string().contains("darwinia-builder-not-installed"),
+			"{}",
+			e
+		);
+		let e = Error::UnsupportedTarget {
+			target: String::from("x86_64-pc-windows-msvc"),
+			host: String::from("x86_64-pc-windows-gnu"),
+		};
+		assert_eq!(
+			e.to_string(),
+			"unsupported TARGET: x86_64-pc-windows-msvc on HOST: x86_64-pc-windows-gnu"
+		);
+
+		// a broken `Cargo.toml` is an error with its path, not a panic
+		fs::write(&path, "[package\nname = \"node\"\n").unwrap();
+		match package_name(&dir).unwrap_err() {
+			Error::Manifest { path: path_, .. } => assert_eq!(path_, path),
+			e => panic!("{}", e),
+		}
+		fs::write(&path, "[package]\nversion = \"0.1.0\"\n").unwrap();
+		assert!(package_name(&dir).unwrap_err().to_string().ends_with("missing `package.name`"));
+		fs::write(&path, "[package]\nname = \"node\"\n").unwrap();
+		assert_eq!(package_name(&dir).unwrap(), "node");
+	}
+}
@@ -0,0 +1,235 @@
+// --- std ---
+use std::{env, fmt, fs};
+// --- custom ---
+use crate::error::Error;
+
+#[allow(non_camel_case_types, unused)]
+#[derive(Clone, Debug)]
+pub enum Arch {
+	aarch64,
+	arm,
+	x86,
+	x86_64,
+}
+
+impl Arch {
+	pub fn detect() -> Result<Self, Error> {
+		if cfg!(target_arch = "x86") {
+			Ok(Arch::x86)
+		} else if cfg!(target_arch = "x86_64") {
+			Ok(Arch::x86_64)
+		} else if cfg!(target_arch = "aarch64") {
+			Ok(Arch::aarch64)
+		} else if cfg!(target_arch = "arm") {
+			Ok(Arch::arm)
+		} else {
+			Err(Error::UnsupportedHost(format!("arch {}", env::consts::ARCH)))
+		}
+	}
+
+	/// The HOST triple e.g. `x86_64-unknown-linux-gnu`
+	pub fn triple(&self, os: &OS) -> String {
+		match (self, os) {
+			// the 32-bit ARM boards are usually ARMv7 with hardware float
+			(Arch::arm, OS::Linux(_)) => String::from("armv7-unknown-linux-gnueabihf"),
+			_ => format!("{}-{}", self, os),
+		}
+	}
+}
+
+impl fmt::Display for Arch {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			Arch::aarch64 => write!(f, "aarch64"),
+			Arch::arm => write!(f, "arm"),
+			Arch::x86 => write!(f, "i686"),
+			Arch::x86_64 => write!(f, "x86_64"),
+		}
+	}
+}
+
+#[allow(non_camel_case_types, clippy::enum_variant_names)]
+#[derive(Clone, Debug)]
+pub enum OS {
+	Linux(LinuxDistribution),
+	macOS,
+	Windows,
+}
+
+impl OS {
+	pub fn detect() -> Result<Self, Error> {
+		if cfg!(target_os = "linux") {
+			Ok(OS::Linux(LinuxDistribution::detect()))
+		} else if cfg!(target_os = "macos") {
+			Ok(OS::macOS)
+		} else if cfg!(target_os = "windows") {
+			Ok(OS::Windows)
+		} else {
+			Err(Error::UnsupportedHost(format!("os {}", env::consts::OS)))
+		}
+	}
+
+	/// The keys of the install hints in `targets.toml`, the most specific one comes first
+	pub fn hint_keys(&self) -> Vec<String> {
+		match self {
+			OS::Linux(distribution) => {
+				let mut keys = vec![format!("linux-{}", distribution.id)];
+				if let Some(family) = distribution.family() {
+					keys.push(family.to_string());
+				}
+				keys.push(String::from("linux"));
+
+				keys
+			}
+			OS::macOS => vec![String::from("macos")],
+			OS::Windows => vec![String::from("windows")],
+		}
+	}
+}
+
+impl fmt::Display for OS {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			OS::Linux(_) => write!(f, "unknown-linux-gnu"),
+			OS::macOS => write!(f, "apple-darwin"),
+			OS::Windows => write!(f, "pc-windows-gnu"),
+		}
+	}
+}
+
+/// The distribution from `/etc/os-release`, see https://www.freedesktop.org/software/systemd/man/os-release.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinuxDistribution {
+	/// e.g. `ubuntu`, `linux` if unknown
+	pub id: String,
+	/// The distributions it is derived from e.g. `["debian"]`
+	pub id_like: Vec<String>,
+	pub version_id: Option<String>,
+}
+
+impl LinuxDistribution {
+	/// Never fails, the unknown distribution still gets the generic `linux` hints
+	pub fn detect() -> Self {
+		["/etc/os-release", "/usr/lib/os-release"]
+			.iter()
+			.find_map(|path| fs::read_to_string(path).ok())
+			.map(|content| Self::parse(&content))
+			.unwrap_or_default()
+	}
+
+	pub fn parse(content: &str) -> Self {
+		let mut distribution = Self::default();
+		for line in content.lines() {
+			let line = line.trim();
+			if line.starts_with('#') {
+				continue;
+			}
+
+			if let Some((k, v)) = line.split_once('=') {
+				let v = v.trim().trim_matches(|c| c == '"' || c == '\'').replace("\\", "");
+				match k.trim() {
+					"ID" if !v.is_empty() => distribution.id = v.to_lowercase(),
+					"ID_LIKE" => distribution.id_like = v.split_whitespace().map(str::to_lowercase).collect(),
+					"VERSION_ID" if !v.is_empty() => distribution.version_id = Some(v),
+					_ => (),
+				}
+			}
+		}
+
+		distribution
+	}
+
+	/// The family of itself or the first known one it is derived from
+	pub fn family(&self) -> Option<Family> {
+		std::iter::once(&self.id)
+			.chain(self.id_like.iter())
+			.find_map(|id| Family::from_id(id))
+	}
+}
+
+impl Default for LinuxDistribution {
+	fn default() -> Self {
+		Self {
+			id: String::from("linux"),
+			id_like: vec![],
+			version_id: None,
+		}
+	}
+}
+
+impl fmt::Display for LinuxDistribution {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		write!(f, "{}", self.id)?;
+		if let Some(version_id) = &self.version_id {
+			write!(f, " {}", version_id)?;
+		}
+		if let Some(family) = self.family() {
+			write!(f, " ({})", family)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// The distributions which share the package manager and the package names
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+	Alpine,
+	Arch,
+	Debian,
+	Rhel,
+	Suse,
+}
+
+impl Family {
+	fn from_id(id: &str) -> Option<Self> {
+		match id {
+			"alpine" => Some(Family::Alpine),
+			"arch" | "archarm" | "manjaro" | "endeavouros" => Some(Family::Arch),
+			"debian" | "ubuntu" | "linuxmint" | "pop" | "raspbian" => Some(Family::Debian),
+			"rhel" | "centos" | "fedora" | "rocky" | "almalinux" | "ol" | "amzn" => Some(Family::Rhel),
+			"suse" | "opensuse" | "sles" | "opensuse-leap" | "opensuse-tumbleweed" => Some(Family::Suse),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for Family {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			Family::Alpine => write!(f, "alpine-like"),
+			Family::Arch => write!(f, "arch-like"),
+			Family::Debian => write!(f, "debian-like"),
+			Family::Rhel => write!(f, "rhel-like"),
+			Family::Suse => write!(f, "suse-like"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_os_release() {
+		let distribution = LinuxDistribution::parse(
+			r#"
+				PRETTY_NAME="Pop!_OS 22.04 LTS"
+				ID=pop
+				ID_LIKE="ubuntu debian"
+				VERSION_ID="22.04"
+			"#,
+		);
+		assert_eq!(distribution.id, "pop");
+		assert_eq!(distribution.id_like, ["ubuntu", "debian"]);
+		assert_eq!(distribution.version_id.as_deref(), Some("22.04"));
+		assert_eq!(distribution.family(), Some(Family::Debian));
+
+		let distribution = LinuxDistribution::parse("ID=nixos\nVERSION_ID='23.05'");
+		assert_eq!(distribution.version_id.as_deref(), Some("23.05"));
+		assert_eq!(distribution.family(), None);
+		assert_eq!(OS::Linux(distribution).hint_keys(), ["linux-nixos", "linux"]);
+
+		assert_eq!(LinuxDistribution::parse(""), LinuxDistribution::default());
+	}
+}
@@ -0,0 +1,42 @@
+extern crate colored;
+extern crate dirs;
+extern crate fs_extra;
+extern crate indicatif;
+extern crate reqwest;
+extern crate serde_json;
+extern crate toml;
+extern crate toml_edit;
+
+pub mod bisect;
+pub mod builder;
+pub mod cargo_config;
+pub mod config;
+pub mod doctor;
+pub mod env_var;
+pub mod error;
+pub mod host;
+pub mod message;
+pub mod mirror;
+pub mod package;
+pub mod rust_toolchain;
+pub mod shell;
+pub mod target;
+pub mod tool;
+pub mod universal;
+
+mod util;
+
+pub use builder::{clean, Builder, Options, Task};
+pub use cargo_config::CargoConfig;
+pub use config::Config;
+pub use doctor::Report;
+pub use env_var::EnvVar;
+pub use error::{Error, Stage};
+pub use message::{MessageFormat, Output};
+pub use mirror::Mirror;
+pub use package::{PackageManager, Plan};
+pub use rust_toolchain::RustToolchain;
+pub use shell::Shell;
+pub use target::{Registry, TargetSpec};
+pub use tool::Tool;
+pub use universal::Universal;
@@ -1,44 +1,27 @@
 #[macro_use]
 extern crate clap;
 extern crate colored;
-extern crate dirs;
-extern crate fs_extra;
-extern crate indicatif;
-#[macro_use]
-extern crate lazy_static;
-extern crate os_info;
-extern crate reqwest;
-extern crate toml;
+extern crate darwinia_builder;
 
 // --- std ---
-use std::{
-	env, fmt,
-	fs::{self, File, OpenOptions},
-	io::{self, Read, Write},
-	path::Path,
-	process::{Command, Stdio},
-};
+use std::{env, path::PathBuf, process};
 // --- external ---
-use clap::{App, Arg, ArgMatches};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use colored::Colorize;
-use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{header::CONTENT_LENGTH, ClientBuilder, Url};
-
-const RUSTUP_UNIX: &'static str = "curl https://sh.rustup.rs -sSf | sh";
-const RUSTUP_WINDOWS: &'static str = "https://www.rust-lang.org/tools/install";
-const WASM_GC: &'static str = "https://github.com/alexcrichton/wasm-gc";
-
-const MACOS_SDK: &'static str =
-	"https://github.com/AurevoirXavier/darwinia-builder/releases/download/MacOSX10.15.sdk/MacOSX10.15.sdk.tar.xz";
-const DARWIN_X86_64_DEPS: &'static str =
-	"https://github.com/AurevoirXavier/darwinia-builder/releases/download/darwin-x86_64/darwin-x86_64.tar.gz";
-const LINUX_X86_64_DEPS: &'static str =
-	"https://github.com/AurevoirXavier/darwinia-builder/releases/download/linux-x86_64/linux-x86_64.tar.gz";
-const WINDOWS_X86_64_DEPS: &'static str =
-	"https://github.com/AurevoirXavier/darwinia-builder/releases/download/windows-x86_64/windows-x86_64.tar.gz";
+// --- custom ---
+use darwinia_builder::{
+	bisect::Date,
+	cargo_config::{self, CARGO_CONFIGS},
+	doctor,
+	host::OS,
+	message::{self, Event, Output, MESSAGE_FORMATS},
+	shell::SHELLS,
+	Builder, Config, Error, MessageFormat, Mirror, Options, Task, Universal,
+};
 
-lazy_static! {
-	static ref APP: ArgMatches<'static> = App::new("darwinia-builder")
+/// `targets` are the supported TARGETs from the registry
+fn app<'a>(targets: &'a [&'a str]) -> App<'a, 'a> {
+	App::new("darwinia-builder")
 		.author("Xavier Lau <c.estlavie@icloud.com>")
 		.about("build tool for substrate")
 		.version(crate_version!())
@@ -47,923 +30,487 @@ lazy_static! {
 				.help("The HOST to build")
 				.long("host")
 				.value_name("HOST")
-				.possible_values(&[
-					// "i686-apple-darwin",
-					"x86_64-apple-darwin",
-					// "i686-unknown-linux-gnu",
-					"x86_64-unknown-linux-gnu",
-					// "i686-pc-windows-gnu",
-					"x86_64-pc-windows-gnu",
-				])
+				.possible_values(targets)
+				.global(true),
 		)
 		.arg(
 			Arg::with_name("target")
 				.help("The TARGET to run")
 				.long("target")
 				.value_name("TARGET")
-				.possible_values(&[
-					// "arm-unknown-linux-gnueabi",
-					// "armv7-unknown-linux-gnueabihf",
-					// "i686-apple-darwin",
-					"x86_64-apple-darwin",
-					// "i686-unknown-linux-gnu",
-					"x86_64-unknown-linux-gnu",
-					// "i686-pc-windows-gnu",
-					"x86_64-pc-windows-gnu",
-				])
+				.possible_values(targets)
+				.global(true),
 		)
 		.arg(
 			Arg::with_name("toolchain")
 				.help("Specify rust toolchain version e.g nightly-2019-07-14")
 				.long("toolchain")
 				.value_name("DATE")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("wasm-toolchain")
+				.help("Specify the rust toolchain of the wasm runtime, `--toolchain` if not set")
+				.long("wasm-toolchain")
+				.value_name("DATE")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("sdk")
+				.help("The Windows SDK/CRT folder of the MSVC TARGETs e.g. `xwin splat --output` folder")
+				.long("sdk")
+				.value_name("DIR")
+				.global(true),
 		)
 		.arg(
 			Arg::with_name("debug")
 				.help("Build project in debug level")
 				.long("debug")
+				.overrides_with("release")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("release")
+				.help("Build project in release level, overrides `debug` of the config file")
+				.long("release")
+				.overrides_with("debug")
+				.global(true),
 		)
 		.arg(
 			Arg::with_name("wasm")
 				.help("Build wasm in release level")
 				.long("wasm")
+				.overrides_with("no-wasm")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("no-wasm")
+				.help("Don't build wasm, overrides `wasm` of the config file")
+				.long("no-wasm")
+				.overrides_with("wasm")
+				.global(true),
 		)
 		.arg(
 			Arg::with_name("pack")
 				.help("Pack <project-name> and LD_LIBRARY into <project-name>.tar.gz (ONLY works on UNIX)")
 				.long("pack")
-				.requires("target")
+				.overrides_with("no-pack")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("no-pack")
+				.help("Don't pack, overrides `pack` of the config file")
+				.long("no-pack")
+				.overrides_with("pack")
+				.global(true),
 		)
 		.arg(
 			Arg::with_name("verbose")
 				.help("Use verbose output (-vv very verbose/build.rs output) while building")
 				.long("verbose")
+				.overrides_with("no-verbose")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("no-verbose")
+				.help("Don't use verbose output, overrides `verbose` of the config file")
+				.long("no-verbose")
+				.overrides_with("verbose")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("install-missing")
+				.help("Install the missing prerequisites (cmake, clang, cross gcc, mingw-w64...) with the package manager")
+				.long("install-missing")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("yes")
+				.help("Answer yes to the package manager while installing the missing prerequisites")
+				.long("yes")
+				.short("y")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("dry-run")
+				.help("Print the install plan of the missing prerequisites without doing anything")
+				.long("dry-run")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("message-format")
+				.help("Print coloured text or JSON events, one per line, see \"Message Format\" in the README")
+				.long("message-format")
+				.value_name("FMT")
+				.possible_values(MESSAGE_FORMATS)
+				.default_value("human")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("ci")
+				.help("No colours or progress bars, fail instead of installing anything implicitly (default if `CI` is set)")
+				.long("ci")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("cargo-config")
+				.help("Set the linker per cargo invocation (env), in the project's .cargo/config.toml or in ~/.cargo/config (global)")
+				.long("cargo-config")
+				.value_name("WHERE")
+				.possible_values(CARGO_CONFIGS)
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("rustup-mirror")
+				.help("Install the toolchains from this rustup dist mirror, a folder or a URL (RUSTUP_DIST_SERVER)")
+				.long("rustup-mirror")
+				.value_name("DIR|URL")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("offline")
+				.help("Never download the toolchains from the network, fail if the mirror doesn't have them")
+				.long("offline")
+				.overrides_with("no-offline")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("no-offline")
+				.help("Allow downloading, overrides `offline` of the config file")
+				.long("no-offline")
+				.overrides_with("offline")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("universal")
+				.help("Build x86_64/aarch64-apple-darwin and merge them into a universal binary with lipo")
+				.long("universal")
+				.conflicts_with("target")
+				.overrides_with("no-universal")
+				.global(true),
+		)
+		.arg(
+			Arg::with_name("no-universal")
+				.help("Don't build the universal binary, overrides `universal` of the config file")
+				.long("no-universal")
+				.overrides_with("universal")
+				.global(true),
+		)
+		.subcommand(SubCommand::with_name("doctor").about("Check the environment of each TARGET without installing/changing anything"))
+		.subcommand(SubCommand::with_name("deps").about("Download the TARGET deps and set up the cargo config file"))
+		.subcommand(
+			SubCommand::with_name("build").about("Build the project (and the wasm with `--wasm`) without packing"),
+		)
+		.subcommand(SubCommand::with_name("wasm").about("Build the wasm only"))
+		.subcommand(SubCommand::with_name("pack").about("Pack the previously built project without rebuilding"))
+		.subcommand(
+			SubCommand::with_name("clean")
+				.about("Remove the packed <project-name> and <project-name>.tar.gz")
+				.arg(
+					Arg::with_name("deps")
+						.help("Remove the downloaded TARGET deps as well")
+						.long("deps"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("env")
+				.about("Print the env vars of the TARGET as shell exports, e.g. `eval \"$(darwinia-builder env --target <TARGET>)\"`")
+				.arg(
+					Arg::with_name("shell")
+						.help("The syntax of the exports, `SHELL` if not set")
+						.long("shell")
+						.value_name("SHELL")
+						.possible_values(SHELLS),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("undo-config")
+				.about("Restore the project's and the global cargo config files to their state before the last run"),
+		)
+		.subcommand(
+			SubCommand::with_name("bisect-toolchain")
+				.about("Binary search the nightlies between the good and the bad dates for the first one which fails to build")
+				.arg(
+					Arg::with_name("good")
+						.help("The nightly which builds e.g. 2020-09-01")
+						.long("good")
+						.value_name("DATE")
+						.validator(|date| date.parse::<Date>().map(drop))
+						.required(true),
+				)
+				.arg(
+					Arg::with_name("bad")
+						.help("The nightly which fails to build e.g. 2020-10-06")
+						.long("bad")
+						.value_name("DATE")
+						.validator(|date| date.parse::<Date>().map(drop))
+						.required(true),
+				),
 		)
-		.get_matches();
-	static ref HOST_ARCH: Arch = if cfg!(target_arch = "x86") {
-		Arch::x86
-	} else if cfg!(target_arch = "x86_64") {
-		Arch::x86_64
-	} else {
-		unreachable!("unsupported arch")
-	};
-	static ref HOST_OS: OS = {
-		let info = os_info::get();
-		match info.os_type() {
-			os_info::Type::Arch => OS::Linux(LinuxDistribution::ArchLinux),
-			os_info::Type::Centos => OS::Linux(LinuxDistribution::CentOS),
-			os_info::Type::Macos => OS::macOS,
-			os_info::Type::Ubuntu => OS::Linux(LinuxDistribution::Ubuntu),
-			os_info::Type::Windows => OS::Windows,
-			os_info::Type::Linux => OS::Linux(LinuxDistribution::Unknown),
-			_ => unreachable!(),
-		}
-	};
-	static ref HOST: String = if let Some(host) = APP.value_of("host") {
-		host.to_owned()
-	} else {
-		format!("{}-{}", *HOST_ARCH, *HOST_OS)
-	};
-	static ref TOOLCHAIN: &'static str = APP.value_of("toolchain").unwrap_or("nightly");
-	static ref IS_CROSS_COMPILE: bool = APP.is_present("target");
 }
 
 fn main() {
-	println!("{} {}", "HOST:".green(), HOST.cyan());
-
-	if let Ok(builder) = Builder::new() {
-		if builder.check() {
-			builder.build().unwrap();
-			if APP.is_present("pack") {
-				builder.pack().unwrap();
-			}
+	let mut output = Output::default();
+	if let Err(e) = start(&mut output) {
+		if output.is_json() {
+			output.emit(Event::Error { error: &e });
+		} else {
+			eprintln!("{} {}", "[✗]".red(), e.to_string().red());
 		}
+		process::exit(e.exit_code());
 	}
 }
 
-#[derive(Debug)]
-struct Builder {
-	tool: Tool,
-	env_var: EnvVar,
-}
-
-impl Builder {
-	fn new() -> Result<Self, io::Error> {
-		Ok(Self {
-			tool: Tool::new()?,
-			env_var: EnvVar::new(),
-		})
+/// `output` is set as soon as the command line is parsed, the errors are printed with it
+fn start(output: &mut Output) -> Result<(), Error> {
+	// the config file might add some TARGETs, so load it before parsing the command line
+	let config = Config::discover()?;
+	let triples = config.registry.triples();
+	let app = app(&triples).get_matches();
+	let (subcommand, matches) = match app.subcommand() {
+		(subcommand, Some(matches)) => (subcommand, matches),
+		_ => ("", &app),
+	};
+	*output = output_of(subcommand, matches);
+	if output.ci {
+		colored::control::set_override(false);
 	}
-
-	fn check(&self) -> bool {
-		let Builder {
-			tool: Tool {
-				rustup,
-				cargo,
-				toolchain,
-				run_target,
-				wasm_target,
-				wasm_gc,
-			},
-			env_var:
-				EnvVar {
-					config_file,
-					target_cc,
-					deps,
-					sysroot,
-					openssl_include_dir,
-					openssl_lib_dir,
-					rocksdb_lib_dir,
-				},
-		} = self;
-
-		let essential_check = ![rustup, cargo, toolchain, wasm_target, wasm_gc, run_target]
-			.iter()
-			.any(|&s| s.is_empty());
-		if *IS_CROSS_COMPILE {
-			// we use rust-native-tls,
-			// which will use the operating system TLS framework if available, meaning Windows and macOS.
-			// On Linux, it will use OpenSSL 1.1.
-			let cross_compile_check = if run_target.contains("linux") {
-				![
-					config_file,
-					target_cc,
-					deps,
-					sysroot,
-					openssl_include_dir,
-					openssl_lib_dir,
-					rocksdb_lib_dir,
-				]
-				.iter()
-				.any(|&s| s.is_empty())
-			} else {
-				![config_file, target_cc, deps, rocksdb_lib_dir]
-					.iter()
-					.any(|&s| s.is_empty())
-			};
-
-			essential_check && cross_compile_check
+	if let Some(path) = &config.path {
+		if output.is_json() {
+			output.emit(Event::Config { path });
 		} else {
-			essential_check
+			output.line(&format!("{} {}", "CONFIG:".green(), path.to_string_lossy().cyan()));
 		}
 	}
 
-	fn build(&self) -> Result<(), io::Error> {
-		if APP.is_present("wasm") {
-			self.build_wasm()?;
-		}
-		self.build_project()?;
+	let universal = if matches.is_present("universal") {
+		Some(true)
+	} else if matches.is_present("no-universal") {
+		Some(false)
+	} else {
+		None
+	};
+	let (universal, targets) = config.targets(matches.value_of("target"), universal);
 
-		Ok(())
+	if subcommand == "undo-config" {
+		return cargo_config::undo(&options(matches, &config, None, output)?.root, output);
 	}
-
-	fn pack(&self) -> Result<(), io::Error> {
-		let is_windows = self.tool.run_target.contains("windows");
-		let root_path = env::current_dir()?;
-		let target_dir = {
-			let mut p = root_path.clone();
-			p.push("target");
-
-			p
-		};
-		let package_name = {
-			let mut cargo_toml = fs::File::open("Cargo.toml")?;
-			let mut config = String::new();
-			cargo_toml.read_to_string(&mut config)?;
-
-			let config = config.parse::<toml::Value>().unwrap();
-			let config = config.as_table().unwrap();
-			let package = config.get("package").unwrap();
-			let package = package.as_table().unwrap();
-
-			package.get("name").unwrap().as_str().unwrap().to_owned()
-		};
-
-		let mut target_path = target_dir.clone();
-		target_path.push(&self.tool.run_target);
-		if APP.is_present("debug") {
-			target_path.push("debug");
-		} else {
-			target_path.push("release");
-		}
-		if is_windows {
-			target_path.push(&format!("{}.exe", &package_name));
-		} else {
-			target_path.push(&package_name);
+	let options = targets
+		.into_iter()
+		.map(|target| options(matches, &config, target, output))
+		.collect::<Result<Vec<_>, _>>()?;
+	// once for all the TARGETs, the config file's `toolchain` is a deliberate pin as well
+	if let (Some(toolchain), Some(rust_toolchain)) = (
+		matches.value_of("toolchain"),
+		options.first().and_then(|options| options.rust_toolchain.as_ref()),
+	) {
+		if toolchain != rust_toolchain.channel {
+			output.missing(
+				"rust-toolchain",
+				&format!(
+					"`--toolchain {}` overrides `{}` pinned by {}",
+					toolchain,
+					rust_toolchain.channel,
+					rust_toolchain.path.to_string_lossy()
+				),
+			);
 		}
-
-		let mut ld_library_dir = root_path.clone();
-		ld_library_dir.push(&self.env_var.deps);
-		ld_library_dir.push("ld-library");
-
-		let mut pack_path = target_dir.clone();
-		pack_path.push(&format!("{}-{}", self.tool.run_target, package_name));
-		let pack_dir = pack_path.clone();
-		if pack_path.is_dir() {
-			fs::remove_dir_all(&pack_path)?;
-		}
-		fs::create_dir(&pack_path)?;
-
-		{
-			if is_windows {
-				pack_path.push(&format!("{}.exe", &package_name));
-			} else {
-				pack_path.push(&package_name);
-			}
-			fs::copy(&target_path, &pack_path)?;
-
-			let mut copy_options = fs_extra::dir::CopyOptions::new();
-			copy_options.overwrite = true;
-			fs_extra::dir::copy(&ld_library_dir, &pack_dir, &copy_options).unwrap();
-
-			drop(target_path);
-			drop(ld_library_dir);
-		}
-
-		if !is_windows {
-			let mut run_script = fs::OpenOptions::new()
-				.create(true)
-				.truncate(true)
-				.write(true)
-				.open(&format!("{}/run.sh", pack_dir.to_string_lossy()))?;
-			run_script.write(
-				format!(
-					"#!/usr/bin/env bash\nexport LD_LIBRARY_PATH=$LD_LIBRARY:$(pwd)/ld-library\n./{}",
-					&package_name
-				)
-				.as_bytes(),
-			)?;
-			run_script.sync_all()?;
-		}
-
-		env::set_current_dir(&target_dir)?;
-		run(Command::new("tar").args(&[
-			"zcf",
-			&format!("{}-{}.tar.gz", self.tool.run_target, &package_name),
-			&format!("{}-{}", self.tool.run_target, &package_name),
-		]))?;
-		env::set_current_dir(&root_path)?;
-
-		Ok(())
 	}
+	if subcommand == "doctor" {
+		print_host(&options[0]);
 
-	fn build_wasm(&self) -> Result<(), io::Error> {
-		let root_path = env::current_dir()?;
-
-		{
-			let mut wasm_path = root_path.clone();
-			wasm_path.push("node/runtime/wasm");
-			env::set_current_dir(&wasm_path)?;
-		}
-
-		run_with_output(
-			Command::new("cargo")
-				.args(&[
-					&format!("+{}", self.tool.toolchain),
-					"rustc",
-					"--release",
-					"--target",
-					"wasm32-unknown-unknown",
-					"--",
-					"-C",
-					"link-arg=--export-table",
-				])
-				.env("CARGO_INCREMENTAL", "0"),
-		)?;
-		run(Command::new("wasm-gc").args(&[
-			"target/wasm32-unknown-unknown/release/node_runtime.wasm",
-			"target/wasm32-unknown-unknown/release/node_runtime.compact.wasm",
-		]))?;
-
-		env::set_current_dir(&root_path)?;
-
-		Ok(())
+		return doctor::report(&options);
 	}
+	let task = task(subcommand, matches);
+	if universal {
+		print_host(&options[0]);
 
-	fn build_project(&self) -> Result<(), io::Error> {
-		let mut build_command = Command::new("cargo");
-
-		build_command.args(&[&format!("+{}", self.tool.toolchain), "rustc"]);
-		if !APP.is_present("debug") {
-			build_command.arg("--release");
-		}
-		if APP.is_present("verbose") {
-			build_command.arg("--verbose");
-		}
-		build_command.env("CARGO_INCREMENTAL", "1");
-		if let Some(target) = APP.value_of("target") {
-			build_command.args(&["--target", target]);
-			build_command.env("TARGET_CC", self.env_var.target_cc.splitn(2, ' ').next().unwrap());
-			build_command.env("ROCKSDB_LIB_DIR", &self.env_var.rocksdb_lib_dir);
-			if self.tool.run_target.contains("linux") {
-				build_command.args(&["--", "-C", &format!("link_args=--sysroot={}", self.env_var.sysroot)]);
-				build_command.env("OPENSSL_INCLUDE_DIR", &self.env_var.openssl_include_dir);
-				build_command.env("OPENSSL_LIB_DIR", &self.env_var.openssl_lib_dir);
-			}
-		}
-
-		run_with_output(&mut build_command)?;
-
-		Ok(())
+		return Universal::run(options, task);
 	}
-}
 
-#[allow(non_camel_case_types, unused)]
-#[derive(Debug)]
-enum Arch {
-	arm,
-	x86,
-	x86_64,
-}
+	for options in options {
+		print_host(&options);
 
-impl fmt::Display for Arch {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-		match self {
-			Arch::arm => write!(f, "arm"),
-			Arch::x86 => write!(f, "i686"),
-			Arch::x86_64 => write!(f, "x86_64"),
-		}
+		Builder::run(options, task)?;
 	}
-}
 
-#[allow(non_camel_case_types)]
-#[derive(Debug)]
-enum OS {
-	Linux(LinuxDistribution),
-	macOS,
-	Windows,
-}
-
-impl fmt::Display for OS {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-		match self {
-			OS::Linux(_) => write!(f, "unknown-linux-gnu"),
-			OS::macOS => write!(f, "apple-darwin"),
-			OS::Windows => write!(f, "pc-windows-gnu"),
-		}
-	}
-}
-
-#[derive(Debug)]
-enum LinuxDistribution {
-	ArchLinux,
-	CentOS,
-	Ubuntu,
-	Unknown,
-}
-
-#[derive(Debug)]
-struct Tool {
-	rustup: String,
-	cargo: String,
-	toolchain: String,
-	run_target: String,
-	wasm_target: String,
-	wasm_gc: String,
+	Ok(())
 }
 
-impl Tool {
-	fn new() -> Result<Self, io::Error> {
-		let mut tool = Self {
-			rustup: String::new(),
-			cargo: String::new(),
-			toolchain: format!("{}-{}", *TOOLCHAIN, *HOST),
-			wasm_target: String::from("wasm32-unknown-unknown"),
-			run_target: APP.value_of("target").unwrap_or(HOST.as_str()).to_owned(),
-			wasm_gc: String::new(),
-		};
-
-		match run(Command::new("rustup").arg("--version")) {
-			Ok(version) => tool.rustup = version,
-			Err(e) => {
-				if e.kind() == io::ErrorKind::NotFound {
-					let os = if cfg!(target_os = "windows") {
-						RUSTUP_WINDOWS
-					} else {
-						RUSTUP_UNIX
-					};
-					eprintln!("{} {}", "[✗] rustup:".red(), os.red());
-					return Err(e);
-				} else {
-					panic!("{}", e);
-				}
+/// The task of the subcommand, `doctor` and `undo-config` are not per TARGET
+fn task(subcommand: &str, matches: &ArgMatches) -> Task {
+	match subcommand {
+		"deps" => Task::Deps,
+		"build" => Task::Build,
+		"wasm" => Task::Wasm,
+		"pack" => Task::Pack,
+		"clean" => Task::Clean {
+			deps: matches.is_present("deps"),
+		},
+		"env" => Task::Env {
+			shell: matches.value_of("shell").and_then(|shell| shell.parse().ok()),
+		},
+		"bisect-toolchain" => {
+			// validated by clap
+			let date = |name| matches.value_of(name).unwrap().parse::<Date>().unwrap();
+
+			Task::Bisect {
+				good: date("good"),
+				bad: date("bad"),
 			}
 		}
-
-		tool.cargo = run(Command::new("cargo").arg("--version")).unwrap();
-		println!("{} {}", "[✓] cargo:".green(), tool.cargo.cyan());
-
-		{
-			let toolchain_list = run(Command::new("rustup").args(&["toolchain", "list"])).unwrap();
-			if !toolchain_list.contains(&tool.toolchain) {
-				eprintln!("{} {}", "[✗] toolchain:".red(), tool.toolchain.red());
-
-				run_with_output(Command::new("rustup").args(&["toolchain", "install", &tool.toolchain])).unwrap();
-			}
-
-			println!("{} {}", "[✓] toolchain:".green(), tool.toolchain.cyan());
-		}
-
-		{
-			let target_list =
-				run(Command::new("rustup").args(&["target", "list", "--toolchain", &tool.toolchain])).unwrap();
-			let mut run_target_installed = false;
-			let mut wasm_target_installed = false;
-
-			for line in target_list.lines() {
-				if line.contains("(installed)") || line.contains("(default)") {
-					if line.contains(&tool.run_target) {
-						run_target_installed = true;
-					} else if line.contains(&tool.wasm_target) {
-						wasm_target_installed = true;
-					}
-				}
-			}
-
-			for (target, target_installed) in [
-				(&tool.run_target, run_target_installed),
-				(&tool.wasm_target, wasm_target_installed),
-			]
-			.iter()
-			{
-				if !target_installed {
-					eprintln!("{} {}", "[✗] target:".red(), target.red());
-
-					run_with_output(Command::new("rustup").args(&[
-						"target",
-						"add",
-						target,
-						"--toolchain",
-						&tool.toolchain,
-					]))
-					.unwrap();
-				}
-
-				println!("{} {}", "[✓] target:".green(), target.cyan());
-			}
-		}
-
-		{
-			if let Err(e) = run(Command::new("wasm-gc").arg("--help")) {
-				if e.kind() == io::ErrorKind::NotFound {
-					eprintln!("{} {}", "[✗] wasm-gc:".red(), WASM_GC.red());
-
-					run_with_output(Command::new("cargo").args(&["install", "--git", WASM_GC])).unwrap();
-				} else {
-					panic!("{}", e);
-				}
-			}
-
-			tool.wasm_gc = String::from(WASM_GC);
-			println!("{} {}", "[✓] wasm-gc:".green(), tool.wasm_gc.cyan());
-		}
-
-		Ok(tool)
+		_ => Task::All,
 	}
 }
 
-#[derive(Debug)]
-struct EnvVar {
-	config_file: String,
-	target_cc: String,
-	deps: String,
-	sysroot: String,
-	openssl_include_dir: String,
-	openssl_lib_dir: String,
-	rocksdb_lib_dir: String,
-}
-
-impl EnvVar {
-	fn new() -> Self {
-		let mut config_file = String::new();
-		let mut target_cc = String::new();
-		let mut deps = String::new();
-		let mut sysroot = String::new();
-		let mut openssl_include_dir = String::new();
-		let mut openssl_lib_dir = String::new();
-		let mut rocksdb_lib_dir = String::new();
-		let mut dir = env::current_dir().unwrap();
-
-		if !*IS_CROSS_COMPILE {
-			return Self {
-				config_file,
-				target_cc,
-				deps,
-				sysroot,
-				openssl_include_dir,
-				openssl_lib_dir,
-				rocksdb_lib_dir,
-			};
-		}
-
-		let host_is_target = APP.value_of("target").unwrap() == *HOST;
-
-		let (mut config_file_handler, config) = {
-			let mut config_file_path = dirs::home_dir().unwrap();
-			config_file_path.push(".cargo");
-			if !config_file_path.as_path().is_dir() {
-				fs::create_dir(&config_file_path).unwrap();
-			}
-
-			config_file_path.push("config");
-			if config_file_path.is_file() {
-				let mut config_file_handler = OpenOptions::new()
-					.read(true)
-					.write(true)
-					.open(&config_file_path)
-					.unwrap();
-				let mut config = String::new();
-				config_file_handler.read_to_string(&mut config).unwrap();
-				(config_file_handler, config)
-			} else {
-				(File::create(&config_file_path).unwrap(), String::new())
-			}
+fn print_host(options: &Options) {
+	let output = &options.output;
+	if output.is_json() {
+		let distribution = match &options.host_os {
+			OS::Linux(distribution) => Some(distribution.to_string()),
+			_ => None,
 		};
-
-		// TODO
-		let target = APP.value_of("target").unwrap();
-		match target {
-			// "arm-unknown-linux-gnueabi" => unimplemented!(),
-			// "armv7-unknown-linux-gnueabihf" => unimplemented!(),
-			// "i686-apple-darwin" => unimplemented!(),
-			"x86_64-apple-darwin" => {
-				let linker = if host_is_target {
-					"clang"
-				} else {
-					"x86_64-apple-darwin19-clang"
-				};
-
-				match run(Command::new(linker).arg("--version")) {
-					Ok(version) => {
-						target_cc = String::from(linker);
-						config_file =
-							String::from("[target.x86_64-apple-darwin]\nlinker = \"x86_64-apple-darwin19-clang\"");
-						set_config_file(
-							&config,
-							&mut config_file,
-							&mut config_file_handler,
-							"[target.x86_64-apple-darwin]",
-						)
-						.unwrap();
-
-						println!(
-							"{} {}{} {}",
-							"[✓]".green(),
-							linker.green(),
-							":".green(),
-							version.split('\n').next().unwrap().cyan()
-						);
-					}
-					Err(e) => {
-						if e.kind() == io::ErrorKind::NotFound {
-							match *HOST_OS {
-								OS::Linux(_) => eprintln!(
-									"{} {}{}\n{}\n{}\n{} {}\n{}\n{}\n{}\n{}\n{}",
-									"[✗]".red(),
-									linker.red(),
-									":".red(),
-									"git clone https://github.com/tpoechtrager/osxcross.git",
-									"cd osxcross",
-									"wget",
-									MACOS_SDK,
-									"mv MacOSX10.15.sdk.tar.xz tarballs",
-									"UNATTENDED=yes ./build.sh",
-									"cd -",
-									"export PATH=$PATH:$(pwd)/osxcross/target/bin",
-									"export LD_LIBRARY_PATH=$LD_LIBRARY_PATH:$(pwd)/osxcross/target/lib",
-								),
-								OS::Windows => unimplemented!(), // TODO
-								_ => unreachable!(),
-							}
-						} else {
-							panic!("{}", e);
-						}
-					}
-				}
-
-				dir.push("darwin-x86_64");
-				check_deps(dir.as_path(), &mut deps, DARWIN_X86_64_DEPS).unwrap();
-			}
-			// "i686-unknown-linux-gnu" => unimplemented!(),
-			"x86_64-unknown-linux-gnu" => {
-				let linker = if host_is_target {
-					"gcc"
-				} else {
-					"x86_64-unknown-linux-gnu-gcc"
-				};
-
-				match run(Command::new(linker).arg("--version")) {
-					Ok(version) => {
-						target_cc = version.splitn(2, '\n').next().unwrap().to_owned();
-						config_file = format!(
-							"[target.x86_64-unknown-linux-gnu]\nlinker = \"{}\"",
-							target_cc.splitn(2, ' ').next().unwrap()
-						);
-						set_config_file(
-							&config,
-							&mut config_file,
-							&mut config_file_handler,
-							"[target.x86_64-unknown-linux-gnu]",
-						)
-						.unwrap();
-
-						println!(
-							"{} {}{} {}",
-							"[✓]".green(),
-							linker.green(),
-							":".green(),
-							target_cc.cyan()
-						);
-					}
-					Err(e) => {
-						if e.kind() == io::ErrorKind::NotFound {
-							match *HOST_OS {
-								OS::macOS => eprintln!(
-									"{} {}{} {}",
-									"[✗]".red(),
-									linker.red(),
-									":".red(),
-									"brew tap SergioBenitez/osxct && brew install x86_64-unknown-linux-gnu".red()
-								),
-								OS::Windows => unimplemented!(), // TODO
-								_ => unreachable!(),
-							}
-						} else {
-							panic!("{}", e);
-						}
-					}
-				}
-
-				dir.push("linux-x86_64");
-				check_deps(dir.as_path(), &mut deps, LINUX_X86_64_DEPS).unwrap();
-			}
-			// "i686-pc-windows-gnu" => unimplemented!(),
-			"x86_64-pc-windows-gnu" => {
-				let linker = if host_is_target {
-					// TODO
-					""
-				} else {
-					"x86_64-w64-mingw32-gcc"
-				};
-
-				match run(Command::new(linker).arg("--version")) {
-					Ok(version) => {
-						target_cc = version.splitn(2, '\n').next().unwrap().to_owned();
-						config_file = format!(
-							"[target.x86_64-pc-windows-gnu]\nlinker = \"{}\"",
-							target_cc.splitn(2, ' ').next().unwrap()
-						);
-						set_config_file(
-							&config,
-							&mut config_file,
-							&mut config_file_handler,
-							"[target.x86_64-pc-windows-gnu]",
-						)
-						.unwrap();
-
-						println!(
-							"{} {}{} {}",
-							"[✓]".green(),
-							linker.green(),
-							":".green(),
-							target_cc.cyan()
-						);
-					}
-					Err(e) => {
-						if e.kind() == io::ErrorKind::NotFound {
-							match *HOST_OS {
-								// TODO
-								OS::Linux(ref distribution) => match distribution {
-									LinuxDistribution::ArchLinux => eprintln!(
-										"{} {}{}\n{}\n{}\n{}{}-{}{}\n{}\n{}\n{}",
-										"[✗]".red(),
-										linker.red(),
-										":".red(),
-										"sudo pacman -S mingw-w64-gcc",
-										"follow https://github.com/rust-lang/rust/issues/48272#issuecomment-429596397:".red(),
-										"cd ~/.rustup/toolchains/",
-										*TOOLCHAIN,
-										*HOST,
-										"/lib/rustlib/x86_64-pc-windows-gnu/lib",
-										"cp /usr/x86_64-w64-mingw32/lib/*crt2.o ./",
-										"sudo ln -s /usr/x86_64-w64-mingw32/lib/libiphlpapi.a /usr/x86_64-w64-mingw32/lib/libIphlpapi.a",
-										"cd -"
-									),
-									LinuxDistribution::CentOS => unimplemented!(),
-									LinuxDistribution::Ubuntu => eprintln!(
-										"{} {}{}\n{}\n{}\n{}{}-{}{}\n{}\n{}\n{}",
-										"[✗]".red(),
-										linker.red(),
-										":".red(),
-										"sudo apt install mingw-w64",
-										"follow https://github.com/rust-lang/rust/issues/48272#issuecomment-429596397:".red(),
-										"cd ~/.rustup/toolchains/",
-										*TOOLCHAIN,
-										*HOST,
-										"/lib/rustlib/x86_64-pc-windows-gnu/lib",
-										"cp /usr/x86_64-w64-mingw32/lib/*crt2.o ./",
-										"sudo ln -s /usr/x86_64-w64-mingw32/lib/libiphlpapi.a /usr/x86_64-w64-mingw32/lib/libIphlpapi.a",
-										"cd -"
-									),
-									LinuxDistribution::Unknown => unimplemented!(),
-								},
-								OS::macOS => eprintln!(
-									"{} {}{}\n{}\n{}\n{}{}-{}{}\n{}\n{}",
-									"[✗]".red(),
-									linker.red(),
-									":".red(),
-									"brew install mingw-w64".red(),
-									"follow https://github.com/rust-lang/rust/issues/48272#issuecomment-429596397:".red(),
-									"cd ~/.rustup/toolchains/",
-									*TOOLCHAIN,
-									*HOST,
-									"/lib/rustlib/x86_64-pc-windows-gnu/lib",
-									"cp -r /usr/local/Cellar/mingw-w64/6.0.0_2/toolchain-x86_64/x86_64-w64-mingw32/lib/*crt2.o ./",
-									"cd -"
-								),
-								_ => unreachable!(),
-							}
-						} else {
-							panic!("{}", e);
-						}
-					}
-				}
-
-				dir.push("windows-x86_64");
-				check_deps(dir.as_path(), &mut deps, WINDOWS_X86_64_DEPS).unwrap();
-			}
-			_ => unreachable!(),
-		}
-
-		if target.contains("linux") {
-			for (k, v, folder) in [
-				("SYSROOT", &mut sysroot, "sysroot"),
-				("OPENSSL_INCLUDE_DIR", &mut openssl_include_dir, "include"),
-				("OPENSSL_LIB_DIR", &mut openssl_lib_dir, "lib/openssl"),
-				("ROCKSDB_LIB_DIR", &mut rocksdb_lib_dir, "lib/rocksdb"),
-			]
-			.iter_mut()
-			{
-				check_envs(k, v, dir.as_path(), folder);
-			}
-		} else {
-			check_envs("ROCKSDB_LIB_DIR", &mut rocksdb_lib_dir, dir.as_path(), "lib/rocksdb");
-		}
-
-		Self {
-			config_file,
-			target_cc,
-			deps,
-			sysroot,
-			openssl_include_dir,
-			openssl_lib_dir,
-			rocksdb_lib_dir,
-		}
+		output.emit(Event::Host {
+			host: &options.host,
+			distribution,
+		});
+	} else if let OS::Linux(distribution) = &options.host_os {
+		output.line(&format!(
+			"{} {} {}",
+			"HOST:".green(),
+			options.host.cyan(),
+			distribution.to_string().cyan()
+		));
+	} else {
+		output.line(&format!("{} {}", "HOST:".green(), options.host.cyan()));
 	}
 }
 
-fn run(command: &mut Command) -> Result<String, io::Error> {
-	match command.output() {
-		Ok(child) => Ok(String::from_utf8_lossy(&child.stdout).trim().to_owned()),
-		Err(e) => Err(e),
+/// Command line flags take precedence over the config file
+/// `--ci` or `CI` of the env turns on the CI mode
+fn output_of(subcommand: &str, matches: &ArgMatches) -> Output {
+	Output {
+		format: matches
+			.value_of("message-format")
+			.and_then(|format| format.parse::<MessageFormat>().ok())
+			.unwrap_or_default(),
+		ci: matches.is_present("ci") || message::ci_from_env(),
+		// keep stdout for the exports
+		stderr: subcommand == "env",
 	}
 }
 
-fn run_with_output(command: &mut Command) -> Result<(), io::Error> {
-	command.stdout(Stdio::piped()).spawn()?.wait_with_output()?;
-	Ok(())
-}
-
-fn set_config_file(
-	config: &str,
-	config_file: &str,
-	config_file_handler: &mut File,
-	target: &str,
-) -> Result<(), io::Error> {
-	let mut config_unset = true;
-
-	for line in config.lines() {
-		if line.trim() == target {
-			config_unset = false;
-		}
+fn options(matches: &ArgMatches, config: &Config, target: Option<String>, output: &Output) -> Result<Options, Error> {
+	let mut options = Options::new(config, target)?;
+	options.output = *output;
+	if let Some(host) = matches.value_of("host") {
+		options.host = host.to_owned();
 	}
-
-	if config_unset {
-		eprintln!("{} {}", "[✗] config file:".red(), "will be set automatically".red());
-
-		if !config.is_empty() {
-			config_file_handler.write("\n\n".as_bytes())?;
-		}
-		config_file_handler.write(config_file.as_bytes())?;
-		config_file_handler.sync_all()?;
+	if let Some(toolchain) = matches.value_of("toolchain") {
+		options.toolchain = toolchain.to_owned();
 	}
-
-	println!(
-		"{} {}",
-		"[✓] config file:".green(),
-		config_file.replace('\n', " ").cyan()
-	);
-
-	Ok(())
-}
-
-fn check_deps(dir: &Path, deps: &mut String, download_link: &str) -> Result<(), io::Error> {
-	if !dir.exists() {
-		eprintln!(
-			"{} {} {}",
-			"[✗] deps:".red(),
-			"automatically download from:".red(),
-			download_link.red(),
-		);
-
-		let download_link = Url::parse(download_link).unwrap();
-		if let Err(e) = download(&download_link) {
-			eprintln!("{} {}", "download failed:".red(), e.to_string().as_str().red());
-		} else {
-			run(Command::new("tar").args(&["xf", download_link.path_segments().unwrap().last().unwrap()]))?;
-
-			*deps = dir.to_string_lossy().to_string();
-			println!("{} {}", "[✓] deps:".green(), deps.cyan());
-		}
-	} else {
-		*deps = dir.to_string_lossy().to_string();
-		println!("{} {}", "[✓] deps:".green(), deps.cyan());
+	if let Some(wasm_toolchain) = matches.value_of("wasm-toolchain") {
+		options.wasm_toolchain = Some(wasm_toolchain.to_owned());
 	}
-
-	Ok(())
-}
-
-fn check_envs(k: &str, v: &mut String, dir: &Path, folder: &str) {
-	if let Ok(v_) = env::var(k) {
-		*v = v_;
-	} else {
-		let mut dir = dir.clone().to_path_buf();
-		dir.push(folder);
-		if dir.as_path().is_dir() {
-			*v = dir.to_string_lossy().to_string();
-
-			println!("{} {}{} {}", "[✓]".green(), (*k).green(), ":".green(), v.cyan());
-		} else {
-			eprintln!("{} {}", "[✗]".red(), (*k).red());
-		}
+	if let Some(sdk) = matches.value_of("sdk") {
+		options.sdk = Some(PathBuf::from(sdk));
+	}
+	if let Some(mirror) = matches.value_of("rustup-mirror") {
+		// relative to where the builder runs, like the other paths of the command line
+		options.mirror = Some(Mirror::new(mirror, &env::current_dir()?));
+	}
+	options.offline = flag(matches, "offline", "no-offline", options.offline);
+	if let Some(cargo_config) = matches
+		.value_of("cargo-config")
+		.and_then(|cargo_config| cargo_config.parse().ok())
+	{
+		options.cargo_config = cargo_config;
+	}
+	options.wasm = flag(matches, "wasm", "no-wasm", options.wasm);
+	options.pack = flag(matches, "pack", "no-pack", options.pack);
+	options.debug = flag(matches, "debug", "release", options.debug);
+	options.verbose = flag(matches, "verbose", "no-verbose", options.verbose);
+	options.install_missing = matches.is_present("install-missing");
+	options.yes = matches.is_present("yes");
+	options.dry_run = matches.is_present("dry-run");
+	// `--install-missing` is explicit, the toolchain/deps/config file installation is not
+	if options.output.ci {
+		options.install = false;
 	}
-}
 
-struct DownloadProgress<R> {
-	inner: R,
-	progress_bar: ProgressBar,
+	Ok(options)
 }
 
-impl<R: Read> Read for DownloadProgress<R> {
-	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-		self.inner.read(buf).map(|n| {
-			self.progress_bar.inc(n as u64);
-			n
-		})
+/// The flag or its negation if either is passed, the config file's value otherwise
+fn flag(matches: &ArgMatches, on: &str, off: &str, config: bool) -> bool {
+	if matches.is_present(on) {
+		true
+	} else if matches.is_present(off) {
+		false
+	} else {
+		config
 	}
 }
 
-fn download(url: &Url) -> Result<(), reqwest::Error> {
-	let client = ClientBuilder::new()
-		.danger_accept_invalid_certs(true)
-		.danger_accept_invalid_hostnames(true)
-		.gzip(true)
-		.use_sys_proxy()
-		.build()?;
-	let total_size = client
-		.get(url.as_str())
-		.send()?
-		.headers()
-		.get(CONTENT_LENGTH)
-		.unwrap()
-		.to_str()
-		.unwrap()
-		.parse()
-		.unwrap();
-	let req = client.get(url.as_str());
+#[test]
+fn test() {}
 
-	let pb = ProgressBar::new(total_size);
-	pb.set_style(
-		ProgressStyle::default_bar()
-			.template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-			.progress_chars("=> "),
-	);
+#[cfg(test)]
+mod tests {
+	// --- std ---
+	use std::iter;
+	// --- custom ---
+	use super::*;
+	use darwinia_builder::Registry;
+
+	#[test]
+	fn subcommand_tasks() {
+		let registry = Registry::builtin();
+		let triples = registry.triples();
+		let task_of = |args: &[&str]| {
+			let app = app(&triples).get_matches_from(iter::once("darwinia-builder").chain(args.iter().copied()));
+			match app.subcommand() {
+				(subcommand, Some(matches)) => task(subcommand, matches),
+				_ => task("", &app),
+			}
+		};
 
-	let file = Path::new(url.path_segments().unwrap().last().unwrap());
-	if file.exists() {
-		fs::remove_file(file).unwrap();
+		// no subcommand runs the whole pipeline like before
+		assert_eq!(task_of(&["--target", "x86_64-unknown-linux-gnu"]), Task::All);
+		assert_eq!(task_of(&["deps"]), Task::Deps);
+		assert_eq!(task_of(&["build", "--wasm"]), Task::Build);
+		assert_eq!(task_of(&["wasm"]), Task::Wasm);
+		assert_eq!(task_of(&["pack", "--target", "x86_64-unknown-linux-gnu"]), Task::Pack);
+		assert_eq!(task_of(&["clean"]), Task::Clean { deps: false });
+		assert_eq!(task_of(&["clean", "--deps"]), Task::Clean { deps: true });
+		assert_eq!(
+			task_of(&["bisect-toolchain", "--good", "2020-09-01", "--bad", "2020-10-06"]),
+			Task::Bisect {
+				good: "2020-09-01".parse().unwrap(),
+				bad: "2020-10-06".parse().unwrap(),
+			}
+		);
+		assert!(app(&triples)
+			.get_matches_from_safe(["darwinia-builder", "pack", "--target", "mips-unknown-linux-gnu"].iter())
+			.is_err());
 	}
 
-	let mut source = DownloadProgress {
-		progress_bar: pb,
-		inner: req.send().unwrap(),
-	};
-	let mut dest = File::create(file).unwrap();
-	io::copy(&mut source, &mut dest).unwrap();
-	dest.sync_all().unwrap();
-
-	Ok(())
+	#[test]
+	fn ci_mode() {
+		let registry = Registry::builtin();
+		let triples = registry.triples();
+		let target = "x86_64-unknown-linux-gnu";
+		let app = app(&triples).get_matches_from(["darwinia-builder", "deps", "--ci", "--target", target].iter());
+		let (subcommand, matches) = app.subcommand();
+		let matches = matches.unwrap();
+		let output = output_of(subcommand, matches);
+		assert!(output.ci);
+		assert!(!output.stderr);
+
+		// report the missing items instead of installing them
+		let config = Config::default();
+		let options = options(matches, &config, Some(target.to_owned()), &output).unwrap();
+		assert!(!options.install);
+		assert!(!options.install_missing);
+	}
 }
-
-#[test]
-fn test() {}
@@ -0,0 +1,401 @@
+// --- std ---
+use std::{
+	env,
+	path::Path,
+	str::FromStr,
+	sync::mpsc::{self, RecvTimeoutError},
+	thread,
+	time::{Duration, Instant},
+};
+// --- external ---
+use colored::Colorize;
+use serde_json::{json, Value};
+// --- custom ---
+use crate::{doctor::Report, error::Error};
+
+/// The version of the JSON events, bumped on any breaking change of them, see "Message Format" in the README
+pub const SCHEMA_VERSION: u32 = 1;
+/// The values of `--message-format`
+pub const MESSAGE_FORMATS: &[&str] = &["human", "json"];
+
+/// How often a running build is reported in the CI mode
+const CI_PROGRESS_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MessageFormat {
+	/// The coloured `[✓]`/`[✗]` lines
+	#[default]
+	Human,
+	/// One JSON event per line on stdout, everything else goes to stderr
+	Json,
+}
+
+impl FromStr for MessageFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"human" => Ok(MessageFormat::Human),
+			"json" => Ok(MessageFormat::Json),
+			_ => Err(format!(
+				"unknown message format `{}`, expect one of {}",
+				s,
+				MESSAGE_FORMATS.join(", ")
+			)),
+		}
+	}
+}
+
+/// Most CI services set `CI`, e.g. GitHub Actions, GitLab CI, Travis CI
+pub fn ci_from_env() -> bool {
+	env::var("CI").is_ok_and(|ci| !ci.is_empty() && ci != "0" && ci != "false")
+}
+
+/// How the messages of a build are printed, see `Options::output`
+///
+/// The colours are process-wide in `colored`, turn them off with `colored::control::set_override` for the CI mode
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Output {
+	pub format: MessageFormat,
+	/// The CI mode: no progress bars but plain progress lines
+	pub ci: bool,
+	/// Print the human lines on stderr, stdout is left to the output of the command e.g. the exports of `env`
+	pub stderr: bool,
+}
+
+impl Output {
+	pub fn is_json(&self) -> bool {
+		self.format == MessageFormat::Json
+	}
+
+	/// A human line on stdout (or stderr, see `stderr`), silent with `--message-format=json`
+	pub fn line(&self, line: &str) {
+		if self.is_json() {
+			return;
+		}
+
+		if self.stderr {
+			eprintln!("{}", line);
+		} else {
+			println!("{}", line);
+		}
+	}
+
+	/// A plain progress line in the CI mode, silent otherwise or with `--message-format=json`
+	pub fn progress(&self, line: &str) {
+		if self.ci {
+			self.line(&format!("[…] {}", line));
+		}
+	}
+
+	/// Print the event with `--message-format=json`, do nothing otherwise
+	pub fn emit(&self, event: Event) {
+		if self.is_json() {
+			println!("{}", event.to_json());
+		}
+	}
+
+	/// `[✓] <name>: <value>`, silent with `--message-format=json`
+	pub fn found(&self, name: &str, value: &str) {
+		self.line(&format!("{} {}", format!("[✓] {}:", name).green(), value.cyan()));
+	}
+
+	/// `[✗] <name>: <fix>` on stderr, silent with `--message-format=json`
+	pub fn missing(&self, name: &str, fix: &str) {
+		if self.is_json() {
+			return;
+		}
+
+		if fix.is_empty() {
+			eprintln!("{}", format!("[✗] {}", name).red());
+		} else if fix.contains('\n') {
+			eprintln!("{}\n{}", format!("[✗] {}:", name).red(), fix);
+		} else {
+			eprintln!("{} {}", format!("[✗] {}:", name).red(), fix.red());
+		}
+	}
+
+	/// Report the tool of the TARGET in both formats
+	pub fn tool(&self, target: &str, name: &str, found: Result<&str, &str>) {
+		match found {
+			Ok(value) => self.found(name, value),
+			Err(fix) => self.missing(name, fix),
+		}
+		self.emit(Event::Tool { target, name, found });
+	}
+
+	/// Report the env var of the TARGET in both formats
+	pub fn env(&self, target: &str, name: &str, found: Result<&str, &str>) {
+		match found {
+			Ok(value) => self.found(name, value),
+			Err(fix) => self.missing(name, fix),
+		}
+		self.emit(Event::Env { target, name, found });
+	}
+
+	/// Run a build of the TARGET, report its start and its finish with the duration
+	///
+	/// The CI mode prints a line every `CI_PROGRESS_INTERVAL` to show the build is still running
+	pub fn build<F: FnOnce() -> Result<(), Error>>(&self, target: &str, kind: &str, f: F) -> Result<(), Error> {
+		self.emit(Event::BuildStarted { target, kind });
+		self.progress(&format!("{} build of {} started", kind, target));
+		let now = Instant::now();
+		let ticker = if self.ci {
+			let (stop, stopped) = mpsc::channel::<()>();
+			let output = *self;
+			let line = format!("{} build of {} is running", kind, target);
+			let handle = thread::spawn(move || {
+				while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(CI_PROGRESS_INTERVAL) {
+					output.progress(&format!("{} ({}s)", line, now.elapsed().as_secs()));
+				}
+			});
+
+			Some((stop, handle))
+		} else {
+			None
+		};
+		let result = f();
+		if let Some((stop, handle)) = ticker {
+			drop(stop);
+			let _ = handle.join();
+		}
+		self.progress(&format!(
+			"{} build of {} {} in {}s",
+			kind,
+			target,
+			if result.is_ok() { "finished" } else { "failed" },
+			now.elapsed().as_secs()
+		));
+		self.emit(Event::BuildFinished {
+			target,
+			kind,
+			success: result.is_ok(),
+			duration: now.elapsed(),
+		});
+
+		result
+	}
+}
+
+/// The events of `--message-format=json`
+#[derive(Debug)]
+pub enum Event<'a> {
+	Config {
+		path: &'a Path,
+	},
+	Host {
+		host: &'a str,
+		distribution: Option<String>,
+	},
+	/// A detected tool, `Err` holds how to install it
+	Tool {
+		target: &'a str,
+		name: &'a str,
+		found: Result<&'a str, &'a str>,
+	},
+	/// A resolved env var (or the deps/config file), `Err` holds how to set it
+	Env {
+		target: &'a str,
+		name: &'a str,
+		found: Result<&'a str, &'a str>,
+	},
+	/// `total` is 0 if the server doesn't tell the size
+	Download {
+		url: &'a str,
+		downloaded: u64,
+		total: u64,
+	},
+	BuildStarted {
+		target: &'a str,
+		kind: &'a str,
+	},
+	BuildFinished {
+		target: &'a str,
+		kind: &'a str,
+		success: bool,
+		duration: Duration,
+	},
+	Artifact {
+		target: &'a str,
+		path: &'a Path,
+	},
+	Plan {
+		target: &'a str,
+		missing: &'a [String],
+		command: Option<String>,
+		unavailable: &'a [String],
+	},
+	Doctor {
+		report: &'a Report,
+	},
+	/// The env vars printed by `env`
+	Exports {
+		target: &'a str,
+		shell: &'a str,
+		vars: &'a [(String, String)],
+	},
+	/// The result of `bisect-toolchain`, `error` is the excerpt of the first bad nightly's build
+	Bisect {
+		good: &'a str,
+		bad: &'a str,
+		skipped: usize,
+		error: &'a str,
+	},
+	Error {
+		error: &'a Error,
+	},
+}
+
+impl Event<'_> {
+	pub fn to_json(&self) -> Value {
+		let found = |found: &Result<&str, &str>| match found {
+			Ok(value) => json!({ "found": true, "value": value }),
+			Err(fix) => json!({ "found": false, "fix": fix }),
+		};
+		let (reason, mut value) = match self {
+			Event::Config { path } => ("config", json!({ "path": path })),
+			Event::Host { host, distribution } => ("host", json!({ "host": host, "distribution": distribution })),
+			Event::Tool {
+				target,
+				name,
+				found: found_,
+			} => (
+				"tool",
+				json!({ "target": target, "name": name, "detection": found(found_) }),
+			),
+			Event::Env {
+				target,
+				name,
+				found: found_,
+			} => (
+				"env",
+				json!({ "target": target, "name": name, "resolution": found(found_) }),
+			),
+			Event::Download { url, downloaded, total } => (
+				"download",
+				json!({ "url": url, "downloaded": downloaded, "total": total }),
+			),
+			Event::BuildStarted { target, kind } => ("build-started", json!({ "target": target, "kind": kind })),
+			Event::BuildFinished {
+				target,
+				kind,
+				success,
+				duration,
+			} => (
+				"build-finished",
+				json!({
+					"target": target,
+					"kind": kind,
+					"success": success,
+					"duration_ms": duration.as_millis() as u64,
+				}),
+			),
+			Event::Artifact { target, path } => ("artifact", json!({ "target": target, "path": path })),
+			Event::Plan {
+				target,
+				missing,
+				command,
+				unavailable,
+			} => (
+				"plan",
+				json!({
+					"target": target,
+					"missing": missing,
+					"command": command,
+					"unavailable": unavailable,
+				}),
+			),
+			Event::Doctor { report } => (
+				"doctor",
+				json!({
+					"target": report.target,
+					"ready": report.is_ready(),
+					"checks": report.checks.iter().map(|check| json!({
+						"category": check.category.to_string(),
+						"name": check.name,
+						"found": check.is_ready(),
+						"value": check.found,
+						"fix": check.fix,
+					})).collect::<Vec<_>>(),
+				}),
+			),
+			Event::Exports { target, shell, vars } => (
+				"exports",
+				json!({
+					"target": target,
+					"shell": shell,
+					"vars": vars.iter().map(|(k, v)| (k.to_owned(), json!(v))).collect::<serde_json::Map<_, _>>(),
+				}),
+			),
+			Event::Bisect {
+				good,
+				bad,
+				skipped,
+				error,
+			} => (
+				"bisect",
+				json!({ "good": good, "bad": bad, "skipped": skipped, "error": error }),
+			),
+			Event::Error { error } => (
+				"error",
+				json!({ "message": error.to_string(), "exit_code": error.exit_code() }),
+			),
+		};
+		value["version"] = json!(SCHEMA_VERSION);
+		value["reason"] = json!(reason);
+
+		value
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ci_of_env() {
+		let ci = env::var_os("CI");
+		for (value, expected) in [
+			(Some("true"), true),
+			(Some("1"), true),
+			(Some("0"), false),
+			(Some("false"), false),
+			(Some(""), false),
+			(None, false),
+		] {
+			match value {
+				Some(value) => env::set_var("CI", value),
+				None => env::remove_var("CI"),
+			}
+			assert_eq!(ci_from_env(), expected, "{:?}", value);
+		}
+		if let Some(ci) = ci {
+			env::set_var("CI", ci);
+		}
+	}
+
+	#[test]
+	fn event_schema() {
+		let event = Event::Tool {
+			target: "x86_64-unknown-linux-gnu",
+			name: "wasm-gc",
+			found: Err("cargo install wasm-gc"),
+		}
+		.to_json();
+		assert_eq!(event["version"], SCHEMA_VERSION);
+		assert_eq!(event["reason"], "tool");
+		assert_eq!(
+			event["detection"],
+			json!({ "found": false, "fix": "cargo install wasm-gc" })
+		);
+
+		let event = Event::BuildFinished {
+			target: "x86_64-unknown-linux-gnu",
+			kind: "native",
+			success: true,
+			duration: Duration::from_millis(1500),
+		}
+		.to_json();
+		assert_eq!(event["duration_ms"], 1500);
+	}
+}
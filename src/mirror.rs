@@ -0,0 +1,132 @@
+// --- std ---
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	process::Command,
+};
+// --- custom ---
+use crate::{bisect::Date, util::resolve_path};
+
+/// A rustup dist mirror, a local folder or a URL with the layout of `static.rust-lang.org`
+///
+/// ```text
+/// <mirror>/dist/2020-10-06/channel-rust-nightly.toml
+/// <mirror>/dist/channel-rust-stable.toml
+/// <mirror>/rustup/dist/<HOST>/rustup-init
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mirror {
+	/// The `RUSTUP_DIST_SERVER`, a `file://` URL for a local folder
+	pub url: String,
+	/// The local folder, its manifests are checked before installing anything
+	pub dir: Option<PathBuf>,
+}
+
+impl Mirror {
+	/// `mirror` is a URL, or a folder relative to `root`
+	pub fn new(mirror: &str, root: &Path) -> Self {
+		if mirror.contains("://") {
+			return Self {
+				url: mirror.trim_end_matches('/').to_owned(),
+				dir: None,
+			};
+		}
+
+		let dir = resolve_path(mirror, root);
+		let path = dir.to_string_lossy().replace('\\', "/");
+		let url = if path.starts_with('/') {
+			format!("file://{}", path.trim_end_matches('/'))
+		} else {
+			// e.g. `C:/mirror`
+			format!("file:///{}", path.trim_end_matches('/'))
+		};
+
+		Self { url, dir: Some(dir) }
+	}
+
+	/// Point rustup to the mirror
+	pub fn apply<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+		command
+			.env("RUSTUP_DIST_SERVER", &self.url)
+			.env("RUSTUP_UPDATE_ROOT", format!("{}/rustup", self.url))
+	}
+
+	/// Check the mirror has the `channel`, and its `package` for the TARGET if any e.g. `("rust-std", "wasm32-unknown-unknown")`
+	///
+	/// Only a local folder can be checked, a URL always passes
+	pub fn check(&self, channel: &str, package: Option<(&str, &str)>) -> Result<(), String> {
+		let dir = match &self.dir {
+			Some(dir) => dir,
+			None => return Ok(()),
+		};
+		let path = manifest_path(dir, channel);
+		let manifest = fs::read_to_string(&path)
+			.map_err(|_| format!("`{}` is not in the mirror, expect {}", channel, path.to_string_lossy()))?
+			.parse::<toml::Value>()
+			.map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+
+		if let Some((package, target)) = package {
+			let targets = manifest
+				.get("pkg")
+				.and_then(|pkg| pkg.get(package))
+				.and_then(|package| package.get("target"));
+			let available = targets
+				.and_then(|targets| targets.get(target).or_else(|| targets.get("*")))
+				.and_then(|target| target.get("available"))
+				.and_then(|available| available.as_bool())
+				.unwrap_or(false);
+			if !available {
+				return Err(format!(
+					"`{}` for {} of `{}` is not in the mirror {}",
+					package,
+					target,
+					channel,
+					dir.to_string_lossy()
+				));
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// `nightly-2020-10-06` is under its date, `nightly`/`stable`/`1.47.0` are the latest ones
+fn manifest_path(dir: &Path, channel: &str) -> PathBuf {
+	let mut path = dir.join("dist");
+	match channel.split_once('-') {
+		Some((name, date)) if date.parse::<Date>().is_ok() => {
+			path.push(date);
+			path.push(format!("channel-rust-{}.toml", name));
+		}
+		_ => path.push(format!("channel-rust-{}.toml", channel)),
+	}
+
+	path
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mirror_layout() {
+		let mirror = Mirror::new("https://mirror.local/rust/", Path::new("/project"));
+		assert_eq!(mirror.url, "https://mirror.local/rust");
+		assert_eq!(mirror.dir, None);
+
+		let mirror = Mirror::new("mirror", Path::new("/project"));
+		assert_eq!(mirror.url, "file:///project/mirror");
+		assert_eq!(
+			manifest_path(mirror.dir.as_ref().unwrap(), "nightly-2020-10-06"),
+			Path::new("/project/mirror/dist/2020-10-06/channel-rust-nightly.toml")
+		);
+		assert_eq!(
+			Mirror::new("~/mirror", Path::new("/project")).dir.unwrap(),
+			dirs::home_dir().unwrap().join("mirror")
+		);
+		assert_eq!(
+			manifest_path(Path::new("/mirror"), "1.47.0"),
+			Path::new("/mirror/dist/channel-rust-1.47.0.toml")
+		);
+	}
+}
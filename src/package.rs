@@ -0,0 +1,411 @@
+// --- std ---
+use std::{fmt, iter, process::Command, str::FromStr};
+// --- custom ---
+use crate::{
+	builder::Options,
+	env_var::detect_linker,
+	error::{Error, Stage},
+	host::{Family, OS},
+	message::{Event, Output},
+	util::{run, run_interactive},
+};
+
+/// The prerequisites of every TARGET, with the command to probe them
+const COMMON_PREREQUISITES: &[(&str, &[&str])] =
+	&[("cmake", &["cmake", "--version"]), ("clang", &["clang", "--version"])];
+
+/// The names of the package managers, the keys of `packages` in a target spec
+pub const PACKAGE_MANAGERS: &[&str] = &["apt", "dnf", "yum", "pacman", "zypper", "apk", "brew"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PackageManager {
+	Apt,
+	Dnf,
+	Yum,
+	Pacman,
+	Zypper,
+	Apk,
+	Brew,
+}
+
+impl PackageManager {
+	/// Choose by the HOST family first, then by the installed one
+	pub fn detect(host_os: &OS) -> Option<Self> {
+		let installed = |program: &str| run(Command::new(program).arg("--version")).is_ok();
+
+		match host_os {
+			OS::macOS => Some(PackageManager::Brew).filter(|_| installed("brew")),
+			OS::Windows => None,
+			OS::Linux(distribution) => match distribution.family() {
+				Some(Family::Debian) => Some(PackageManager::Apt),
+				Some(Family::Rhel) if installed("dnf") => Some(PackageManager::Dnf),
+				Some(Family::Rhel) => Some(PackageManager::Yum),
+				Some(Family::Arch) => Some(PackageManager::Pacman),
+				Some(Family::Suse) => Some(PackageManager::Zypper),
+				Some(Family::Alpine) => Some(PackageManager::Apk),
+				None => [
+					("apt-get", PackageManager::Apt),
+					("dnf", PackageManager::Dnf),
+					("yum", PackageManager::Yum),
+					("pacman", PackageManager::Pacman),
+					("zypper", PackageManager::Zypper),
+					("apk", PackageManager::Apk),
+				]
+				.iter()
+				.find(|(program, _)| installed(program))
+				.map(|(_, manager)| *manager),
+			},
+		}
+	}
+
+	/// The packages of the prerequisite on the HOST (a triple), `None` if it is not packaged for this package manager,
+	/// a TARGET declares the packages of its own prerequisites with `packages` of its spec
+	pub fn packages(self, prerequisite: &str, host: &str) -> Option<&'static [&'static str]> {
+		use PackageManager::*;
+
+		// the native gcc builds for x86_64 on an x86_64 HOST only
+		let x86_64_host = host.starts_with("x86_64-");
+
+		let packages: &[&str] = match (prerequisite, self) {
+			("cmake", _) => &["cmake"],
+			("git", _) => &["git"],
+			("clang", Apt) => &["clang", "libclang-dev"],
+			("clang", Dnf) | ("clang", Yum) | ("clang", Zypper) => &["clang", "clang-devel"],
+			("clang", Apk) => &["clang", "clang-dev"],
+			("clang", Pacman) => &["clang"],
+			("clang", Brew) | ("lld", Brew) | ("llvm", Brew) => &["llvm"],
+			("lld", _) => &["lld"],
+			("llvm", _) => &["llvm"],
+			("openssl-dev", Apt) => &["libssl-dev", "pkg-config"],
+			("openssl-dev", Dnf) | ("openssl-dev", Yum) => &["openssl-devel", "pkgconf-pkg-config"],
+			("openssl-dev", Pacman) => &["openssl", "pkgconf"],
+			("openssl-dev", Zypper) => &["libopenssl-devel", "pkg-config"],
+			("openssl-dev", Apk) => &["openssl-dev", "pkgconf"],
+			("openssl-dev", Brew) => &["openssl", "pkg-config"],
+			("rocksdb-dev", Apt) => &["librocksdb-dev"],
+			("rocksdb-dev", Dnf) | ("rocksdb-dev", Yum) | ("rocksdb-dev", Zypper) => &["rocksdb-devel"],
+			("rocksdb-dev", Apk) => &["rocksdb-dev"],
+			("rocksdb-dev", Pacman) | ("rocksdb-dev", Brew) => &["rocksdb"],
+			("gcc-x86_64", Brew) => &["SergioBenitez/osxct/x86_64-unknown-linux-gnu"],
+			("gcc-x86_64", _) if x86_64_host => &["gcc"],
+			("gcc-x86_64", Apt) => &["gcc-x86-64-linux-gnu"],
+			("gcc-x86_64", Dnf) | ("gcc-x86_64", Yum) => &["gcc-x86_64-linux-gnu"],
+			("gcc-multilib", Apt) => &["gcc-multilib"],
+			("gcc-multilib", Dnf) | ("gcc-multilib", Yum) => &["glibc-devel.i686", "libgcc.i686"],
+			("gcc-multilib", Pacman) => &["lib32-glibc", "lib32-gcc-libs"],
+			("gcc-multilib", Zypper) => &["gcc-32bit", "glibc-devel-32bit"],
+			("gcc-multilib", Brew) => &["messense/macos-cross-toolchains/i686-unknown-linux-gnu"],
+			("gcc-aarch64", Apt) => &["gcc-aarch64-linux-gnu"],
+			("gcc-aarch64", Dnf) | ("gcc-aarch64", Yum) => &["gcc-aarch64-linux-gnu"],
+			("gcc-aarch64", Pacman) => &["aarch64-linux-gnu-gcc"],
+			("gcc-aarch64", Brew) => &["SergioBenitez/osxct/aarch64-unknown-linux-gnu"],
+			("gcc-armhf", Apt) => &["gcc-arm-linux-gnueabihf"],
+			("gcc-armhf", Brew) => &["messense/macos-cross-toolchains/armv7-unknown-linux-gnueabihf"],
+			("gcc-armel", Apt) => &["gcc-arm-linux-gnueabi"],
+			("musl", Apt) => &["musl-tools"],
+			("musl", Dnf) | ("musl", Yum) => &["musl-gcc"],
+			("musl", Pacman) => &["musl"],
+			("musl", Apk) => &["musl-dev"],
+			("musl", Brew) => &["FiloSottile/musl-cross/musl-cross"],
+			("mingw-w64", Apt) | ("mingw-w64-i686", Apt) => &["mingw-w64"],
+			("mingw-w64", Dnf) | ("mingw-w64", Yum) => &["mingw64-gcc"],
+			("mingw-w64-i686", Dnf) | ("mingw-w64-i686", Yum) => &["mingw32-gcc"],
+			("mingw-w64", Pacman) | ("mingw-w64-i686", Pacman) => &["mingw-w64-gcc"],
+			("mingw-w64", Zypper) => &["mingw64-cross-gcc"],
+			("mingw-w64-i686", Zypper) => &["mingw32-cross-gcc"],
+			("mingw-w64", Brew) | ("mingw-w64-i686", Brew) => &["mingw-w64"],
+			_ => return None,
+		};
+
+		Some(packages)
+	}
+
+	/// The install command, `yes` answers the confirmations
+	pub fn install_command(self, packages: &[impl AsRef<str>], yes: bool) -> Command {
+		use PackageManager::*;
+
+		let mut args = match self {
+			Apt => vec!["apt-get", "install"],
+			Dnf => vec!["dnf", "install"],
+			Yum => vec!["yum", "install"],
+			Pacman => vec!["pacman", "-S", "--needed"],
+			Zypper => vec!["zypper", "install"],
+			Apk => vec!["apk", "add"],
+			Brew => vec!["brew", "install"],
+		};
+		if yes {
+			match self {
+				Apt | Dnf | Yum => args.push("-y"),
+				Pacman => args.push("--noconfirm"),
+				Zypper => args.insert(1, "--non-interactive"),
+				Apk | Brew => (),
+			}
+		}
+		args.extend(packages.iter().map(AsRef::as_ref));
+
+		// Homebrew refuses to run as root
+		let mut command = if self == Brew || is_root() {
+			Command::new(args.remove(0))
+		} else {
+			Command::new("sudo")
+		};
+		command.args(args);
+
+		command
+	}
+
+	/// The install command as a shell line
+	pub fn install_line(self, packages: &[impl AsRef<str>], yes: bool) -> String {
+		let command = self.install_command(packages, yes);
+
+		iter::once(command.get_program())
+			.chain(command.get_args())
+			.map(|arg| arg.to_string_lossy())
+			.collect::<Vec<_>>()
+			.join(" ")
+	}
+
+	/// The shell line to install the prerequisite on the HOST, `None` if it is not packaged
+	pub fn install_hint(self, prerequisite: &str, host: &str) -> Option<String> {
+		self.packages(prerequisite, host)
+			.map(|packages| self.install_line(packages, false))
+	}
+}
+
+impl FromStr for PackageManager {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"apt" => Ok(PackageManager::Apt),
+			"dnf" => Ok(PackageManager::Dnf),
+			"yum" => Ok(PackageManager::Yum),
+			"pacman" => Ok(PackageManager::Pacman),
+			"zypper" => Ok(PackageManager::Zypper),
+			"apk" => Ok(PackageManager::Apk),
+			"brew" => Ok(PackageManager::Brew),
+			_ => Err(format!(
+				"unknown package manager `{}`, expect one of {}",
+				s,
+				PACKAGE_MANAGERS.join(", ")
+			)),
+		}
+	}
+}
+
+impl fmt::Display for PackageManager {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			PackageManager::Apt => write!(f, "apt"),
+			PackageManager::Dnf => write!(f, "dnf"),
+			PackageManager::Yum => write!(f, "yum"),
+			PackageManager::Pacman => write!(f, "pacman"),
+			PackageManager::Zypper => write!(f, "zypper"),
+			PackageManager::Apk => write!(f, "apk"),
+			PackageManager::Brew => write!(f, "brew"),
+		}
+	}
+}
+
+/// The missing prerequisites of a TARGET and how to install them
+#[derive(Debug)]
+pub struct Plan {
+	pub target: String,
+	pub manager: Option<PackageManager>,
+	/// The missing prerequisites
+	pub missing: Vec<String>,
+	pub packages: Vec<String>,
+	/// The missing prerequisites which are not packaged, install them manually
+	pub unavailable: Vec<String>,
+}
+
+impl Plan {
+	pub fn new(options: &Options) -> Result<Self, Error> {
+		let mut missing = vec![];
+		for (prerequisite, probe) in COMMON_PREREQUISITES {
+			if run(Command::new(probe[0]).args(&probe[1..])).is_err() {
+				missing.push((*prerequisite).to_owned());
+			}
+		}
+		if let OS::Linux(_) = options.host_os {
+			if run(Command::new("pkg-config").args(["--exists", "openssl"])).is_err() {
+				missing.push(String::from("openssl-dev"));
+			}
+		}
+		let manager = PackageManager::detect(&options.host_os);
+		let mut packages = vec![];
+		// the prerequisites installed with the packages declared by the TARGET
+		let mut declared = vec![];
+		if let Some(spec) = &options.target_spec {
+			let mut linker_found = false;
+			for linker in spec.linkers(&options.host) {
+				if detect_linker(linker)?.is_some() {
+					linker_found = true;
+					break;
+				}
+			}
+			let cc_found = if let Some(cc) = &spec.cc {
+				detect_linker(cc)?.is_some()
+			} else {
+				true
+			};
+
+			if !linker_found || !cc_found {
+				for prerequisite in &spec.requires {
+					if !missing.contains(prerequisite) {
+						missing.push(prerequisite.to_owned());
+					}
+				}
+				if let Some(packages_) = manager.and_then(|manager| spec.packages(manager)) {
+					packages.extend_from_slice(packages_);
+					declared.extend_from_slice(&spec.requires);
+				}
+			}
+		}
+
+		let mut unavailable = vec![];
+		for prerequisite in missing.iter().filter(|prerequisite| !declared.contains(prerequisite)) {
+			match manager.and_then(|manager| manager.packages(prerequisite, &options.host)) {
+				Some(packages_) => {
+					for package in packages_ {
+						if !packages.iter().any(|package_| package_ == package) {
+							packages.push((*package).to_owned());
+						}
+					}
+				}
+				None => unavailable.push(prerequisite.to_owned()),
+			}
+		}
+
+		Ok(Self {
+			target: options.run_target().to_owned(),
+			manager,
+			missing,
+			packages,
+			unavailable,
+		})
+	}
+
+	pub fn print(&self, output: &Output, yes: bool) {
+		let command = match self.manager {
+			Some(manager) if !self.packages.is_empty() => Some(manager.install_line(&self.packages, yes)),
+			_ => None,
+		};
+		output.emit(Event::Plan {
+			target: &self.target,
+			missing: &self.missing,
+			command: command.clone(),
+			unavailable: &self.unavailable,
+		});
+
+		if self.missing.is_empty() {
+			output.found("prerequisites", "nothing to install");
+			return;
+		}
+
+		output.missing("prerequisites", &self.missing.join(", "));
+		if let Some(command) = command {
+			output.found("install plan", &command);
+		}
+		if !self.unavailable.is_empty() {
+			output.missing(
+				"install manually",
+				&format!("{} (see the hints above)", self.unavailable.join(", ")),
+			);
+		}
+	}
+
+	/// Run the plan, fail if anything must be installed manually
+	pub fn install(&self, output: &Output, yes: bool) -> Result<(), Error> {
+		if let Some(mut command) = self.command(yes) {
+			run_interactive(&mut command, output)?;
+		}
+		if self.unavailable.is_empty() {
+			Ok(())
+		} else {
+			Err(Error::Missing(self.unavailable.clone()))
+		}
+	}
+
+	fn command(&self, yes: bool) -> Option<Command> {
+		match self.manager {
+			Some(manager) if !self.packages.is_empty() => Some(manager.install_command(&self.packages, yes)),
+			_ => None,
+		}
+	}
+}
+
+/// Print (with `options.dry_run`) or run (with `options.install_missing`) the install plan of the TARGET,
+/// return `false` if it is a dry run, nothing should be done after it
+pub fn install_missing(options: &Options) -> Result<bool, Error> {
+	if !(options.dry_run || options.install_missing) {
+		return Ok(true);
+	}
+
+	let plan = options.stage(Stage::Deps, || Plan::new(options))?;
+	plan.print(&options.output, options.yes);
+	if options.dry_run {
+		return Ok(false);
+	}
+
+	options.stage(Stage::Deps, || plan.install(&options.output, options.yes))?;
+
+	Ok(true)
+}
+
+fn is_root() -> bool {
+	run(Command::new("id").arg("-u")).is_ok_and(|uid| uid == "0")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn install_command() {
+		let command = PackageManager::Zypper.install_command(&["cmake", "lld"], true);
+		let args = format!("{:?}", command);
+		assert!(
+			args.ends_with(r#""zypper" "--non-interactive" "install" "cmake" "lld""#),
+			"{}",
+			args
+		);
+
+		let line = PackageManager::Apt.install_line(&["cmake", "gcc-aarch64-linux-gnu"], true);
+		assert!(
+			line.ends_with("apt-get install -y cmake gcc-aarch64-linux-gnu"),
+			"{}",
+			line
+		);
+
+		let host = "x86_64-unknown-linux-gnu";
+		assert_eq!(
+			PackageManager::Apt.packages("mingw-w64-i686", host),
+			PackageManager::Apt.packages("mingw-w64", host)
+		);
+		assert_eq!(PackageManager::Apk.packages("gcc-armhf", host), None);
+	}
+
+	#[test]
+	fn gcc_x86_64_by_host() {
+		assert_eq!(
+			PackageManager::Apt.packages("gcc-x86_64", "x86_64-unknown-linux-gnu"),
+			Some(&["gcc"][..])
+		);
+		assert_eq!(
+			PackageManager::Apt.packages("gcc-x86_64", "aarch64-unknown-linux-gnu"),
+			Some(&["gcc-x86-64-linux-gnu"][..])
+		);
+		assert_eq!(
+			PackageManager::Dnf.packages("gcc-x86_64", "aarch64-unknown-linux-gnu"),
+			Some(&["gcc-x86_64-linux-gnu"][..])
+		);
+		assert_eq!(PackageManager::Pacman.packages("gcc-x86_64", "aarch64-unknown-linux-gnu"), None);
+		assert_eq!(
+			PackageManager::Brew.packages("gcc-x86_64", "x86_64-apple-darwin"),
+			PackageManager::Brew.packages("gcc-x86_64", "aarch64-apple-darwin")
+		);
+		// declared by the TARGET, see `target::tests::declared_packages`
+		assert_eq!(PackageManager::Apt.packages("gcc-powerpc64le", "x86_64-unknown-linux-gnu"), None);
+	}
+}
@@ -0,0 +1,160 @@
+// --- std ---
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+// --- custom ---
+use crate::config::ConfigError;
+
+/// The legacy one-line file, it takes precedence over `rust-toolchain.toml` like in rustup
+pub const RUST_TOOLCHAIN: &str = "rust-toolchain";
+pub const RUST_TOOLCHAIN_TOML: &str = "rust-toolchain.toml";
+
+/// The toolchain pinned by the project in `rust-toolchain` or `rust-toolchain.toml`
+///
+/// ```toml
+/// [toolchain]
+/// channel = "nightly-2020-10-06"
+/// components = ["rustfmt", "rust-src"]
+/// targets = ["wasm32-unknown-unknown"]
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RustToolchain {
+	pub path: PathBuf,
+	pub channel: String,
+	pub components: Vec<String>,
+	pub targets: Vec<String>,
+}
+
+impl RustToolchain {
+	/// Look for the nearest file upwards from `dir`, like rustup does
+	pub fn discover(dir: &Path) -> Result<Option<Self>, ConfigError> {
+		for dir in dir.ancestors() {
+			for file in [RUST_TOOLCHAIN, RUST_TOOLCHAIN_TOML] {
+				let path = dir.join(file);
+				if path.is_file() {
+					return Self::load(&path).map(Some);
+				}
+			}
+		}
+
+		Ok(None)
+	}
+
+	pub fn load(path: &Path) -> Result<Self, ConfigError> {
+		let content = fs::read_to_string(path).map_err(|e| ConfigError::new(path, None, e.to_string()))?;
+		let is_toml = path.extension().is_some_and(|extension| extension == "toml");
+		let mut rust_toolchain =
+			Self::parse(&content, is_toml).map_err(|(key, reason)| ConfigError::new(path, key, reason))?;
+		rust_toolchain.path = path.to_path_buf();
+
+		Ok(rust_toolchain)
+	}
+
+	/// Both files might be in the TOML format or the legacy one, which is the channel only
+	///
+	/// A `.toml` file or one with a `[toolchain]` table is TOML, its syntax error is reported as it is
+	fn parse(content: &str, is_toml: bool) -> Result<Self, (Option<String>, String)> {
+		let toolchain = match content.parse::<toml::Value>() {
+			Ok(value) => value
+				.get("toolchain")
+				.cloned()
+				.ok_or((None, String::from("expect a `[toolchain]` table")))?,
+			Err(e) if is_toml || content.lines().any(|line| line.trim() == "[toolchain]") => {
+				return Err((None, e.to_string()));
+			}
+			Err(_) => {
+				let channel = content.trim();
+				if channel.is_empty() || channel.contains(char::is_whitespace) {
+					return Err((None, String::from("expect a channel e.g. `nightly-2020-10-06`")));
+				}
+
+				return Ok(Self {
+					channel: channel.to_owned(),
+					..Default::default()
+				});
+			}
+		};
+		let strings = |key: &str| -> Result<Vec<String>, (Option<String>, String)> {
+			let error = || {
+				(
+					Some(format!("toolchain.{}", key)),
+					String::from("expect an array of strings"),
+				)
+			};
+
+			match toolchain.get(key) {
+				Some(value) => value
+					.as_array()
+					.ok_or_else(error)?
+					.iter()
+					.map(|v| v.as_str().map(ToOwned::to_owned).ok_or_else(error))
+					.collect(),
+				None => Ok(vec![]),
+			}
+		};
+
+		if toolchain.get("path").is_some() {
+			return Err((
+				Some(String::from("toolchain.path")),
+				String::from("custom toolchains are not supported, use `channel`"),
+			));
+		}
+
+		Ok(Self {
+			channel: toolchain
+				.get("channel")
+				.and_then(|channel| channel.as_str())
+				.map(ToOwned::to_owned)
+				.ok_or((Some(String::from("toolchain.channel")), String::from("expect a string")))?,
+			components: strings("components")?,
+			targets: strings("targets")?,
+			..Default::default()
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_rust_toolchain() {
+		let rust_toolchain = RustToolchain::parse("nightly-2020-10-06\n", false).unwrap();
+		assert_eq!(rust_toolchain.channel, "nightly-2020-10-06");
+		assert!(rust_toolchain.components.is_empty());
+
+		let rust_toolchain = RustToolchain::parse(
+			r#"
+			[toolchain]
+			channel = "nightly-2020-10-06"
+			components = ["rustfmt", "rust-src"]
+			targets = ["wasm32-unknown-unknown"]
+			"#,
+			false,
+		)
+		.unwrap();
+		assert_eq!(rust_toolchain.channel, "nightly-2020-10-06");
+		assert_eq!(rust_toolchain.components, ["rustfmt", "rust-src"]);
+		assert_eq!(rust_toolchain.targets, ["wasm32-unknown-unknown"]);
+
+		assert!(RustToolchain::parse("[toolchain]\ncomponents = [\"rustfmt\"]", false).is_err());
+		assert!(RustToolchain::parse("[toolchain]\npath = \"/opt/rust\"", false).is_err());
+	}
+
+	#[test]
+	fn toml_syntax_error() {
+		let legacy = String::from("expect a channel e.g. `nightly-2020-10-06`");
+		// the legacy file with a typo in its TOML
+		let (key, reason) = RustToolchain::parse("[toolchain]\nchannel = nightly-2020-10-06\n", false).unwrap_err();
+		assert_eq!(key, None);
+		assert_ne!(reason, legacy);
+		assert!(reason.contains("line 2"), "{}", reason);
+		// `rust-toolchain.toml` is always TOML
+		let (_, reason) = RustToolchain::parse("nightly 2020-10-06\n", true).unwrap_err();
+		assert_ne!(reason, legacy);
+		assert!(RustToolchain::parse("nightly-2020-10-06\n", true).is_err());
+		// the legacy format otherwise
+		assert_eq!(RustToolchain::parse("nightly 2020-10-06\n", false).unwrap_err(), (None, legacy));
+	}
+}
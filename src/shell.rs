@@ -0,0 +1,82 @@
+// --- std ---
+use std::{env, fmt, str::FromStr};
+
+/// The values of `env --shell`
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell", "dotenv"];
+
+/// The syntax of the exports printed by `env`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shell {
+	/// bash and zsh, `export K='v'`
+	Posix,
+	/// `set -gx K 'v'`
+	Fish,
+	/// `$env:K = 'v'`
+	PowerShell,
+	/// A `.env` file, `K="v"`
+	Dotenv,
+}
+
+impl Shell {
+	/// The shell of `SHELL`, PowerShell on Windows
+	pub fn detect() -> Self {
+		if cfg!(target_os = "windows") {
+			return Shell::PowerShell;
+		}
+
+		match env::var("SHELL") {
+			Ok(shell) if shell.ends_with("fish") => Shell::Fish,
+			_ => Shell::Posix,
+		}
+	}
+
+	/// The line which sets the env var
+	pub fn export(self, k: &str, v: &str) -> String {
+		match self {
+			Shell::Posix => format!("export {}='{}'", k, v.replace('\'', r"'\''")),
+			Shell::Fish => format!("set -gx {} '{}'", k, v.replace('\\', r"\\").replace('\'', r"\'")),
+			Shell::PowerShell => format!("$env:{} = '{}'", k, v.replace('\'', "''")),
+			Shell::Dotenv => format!("{}=\"{}\"", k, v.replace('\\', r"\\").replace('"', "\\\"")),
+		}
+	}
+}
+
+impl FromStr for Shell {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"bash" | "zsh" => Ok(Shell::Posix),
+			"fish" => Ok(Shell::Fish),
+			"powershell" => Ok(Shell::PowerShell),
+			"dotenv" => Ok(Shell::Dotenv),
+			_ => Err(format!("unknown shell `{}`, expect one of {}", s, SHELLS.join(", "))),
+		}
+	}
+}
+
+impl fmt::Display for Shell {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+		match self {
+			Shell::Posix => write!(f, "bash"),
+			Shell::Fish => write!(f, "fish"),
+			Shell::PowerShell => write!(f, "powershell"),
+			Shell::Dotenv => write!(f, "dotenv"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn quote_exports() {
+		let v = r"C:\it's";
+		assert_eq!(Shell::Posix.export("K", v), r"export K='C:\it'\''s'");
+		assert_eq!(Shell::Fish.export("K", v), r"set -gx K 'C:\\it\'s'");
+		assert_eq!(Shell::PowerShell.export("K", v), r"$env:K = 'C:\it''s'");
+		assert_eq!(Shell::Dotenv.export("K", "a \"b\""), r#"K="a \"b\"""#);
+		assert_eq!("zsh".parse::<Shell>().unwrap(), Shell::Posix);
+	}
+}
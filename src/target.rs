@@ -0,0 +1,372 @@
+// --- std ---
+use std::collections::BTreeMap;
+// --- custom ---
+use crate::{host::OS, package::PackageManager};
+
+const BUILTIN_TARGETS: &str = include_str!("targets.toml");
+/// `{mingw-crt2}` of the hints, rustc's mingw `crt2.o` doesn't match the cross gcc,
+/// see https://github.com/rust-lang/rust/issues/48272#issuecomment-429596397
+const MINGW_CRT2: &str = "follow https://github.com/rust-lang/rust/issues/48272#issuecomment-429596397:
+cd ~/.rustup/toolchains/{toolchain}-{host}/lib/rustlib/{triple}/lib
+cp {mingw-lib}/*crt2.o ./";
+/// The import library is looked up as `libIphlpapi.a` on a case-sensitive file system
+const MINGW_IPHLPAPI: &str = "sudo ln -s {mingw-lib}/libiphlpapi.a {mingw-lib}/libIphlpapi.a";
+
+/// Everything needed to cross compile to a TARGET, see `targets.toml`
+#[derive(Clone, Debug, Default)]
+pub struct TargetSpec {
+	pub triple: String,
+	/// The linker while HOST is TARGET, fallback to `linkers` if not set
+	pub native_linker: Option<String>,
+	/// The cross linker candidates, the first one found is used
+	pub linkers: Vec<String>,
+	/// The C compiler, fallback to the linker if not set
+	pub cc: Option<String>,
+	/// The archiver for the C deps
+	pub ar: Option<String>,
+	/// The deps folder and its download link, `None` if the bundle is not released and must be built by hand
+	pub deps: Option<(String, Option<String>)>,
+	/// The required env vars and their folder in the deps
+	pub envs: Vec<(String, String)>,
+	/// The env vars made of the folders in the SDK, joined by `;`
+	pub sdk_envs: Vec<(String, Vec<String>)>,
+	/// The prerequisites of the linker (and the C compiler), see `PackageManager::packages`
+	pub requires: Vec<String>,
+	/// The packages of `requires`, keyed by package manager, instead of the built-in ones
+	pub packages: BTreeMap<String, Vec<String>>,
+	/// How to install the linker, keyed by HOST
+	pub hints: BTreeMap<String, String>,
+	/// Link everything statically, pack the binary without ld-library
+	pub static_link: bool,
+}
+
+impl TargetSpec {
+	pub fn new(triple: &str) -> Self {
+		Self {
+			triple: triple.to_owned(),
+			..Default::default()
+		}
+	}
+
+	/// Return `Ok(false)` if the key is not a target spec key
+	pub fn set(&mut self, key: &str, value: &toml::Value) -> Result<bool, (String, String)> {
+		let error = |reason: &str| (key.to_owned(), reason.to_owned());
+		let string = |value: &toml::Value| {
+			value
+				.as_str()
+				.map(ToOwned::to_owned)
+				.ok_or_else(|| error("expect a string"))
+		};
+		let table = |value: &toml::Value| -> Result<Vec<(String, String)>, (String, String)> {
+			value
+				.as_table()
+				.ok_or_else(|| error("expect a table of strings"))?
+				.iter()
+				.map(|(k, v)| {
+					v.as_str()
+						.map(|v| (k.to_owned(), v.to_owned()))
+						.ok_or_else(|| (format!("{}.{}", key, k), String::from("expect a string")))
+				})
+				.collect()
+		};
+
+		match key {
+			"native-linker" => self.native_linker = Some(string(value)?),
+			"linkers" => {
+				self.linkers = value
+					.as_array()
+					.ok_or_else(|| error("expect an array of linkers"))?
+					.iter()
+					.map(string)
+					.collect::<Result<_, _>>()?
+			}
+			"requires" => {
+				self.requires = value
+					.as_array()
+					.ok_or_else(|| error("expect an array of prerequisites"))?
+					.iter()
+					.map(string)
+					.collect::<Result<_, _>>()?
+			}
+			"cc" => self.cc = Some(string(value)?),
+			"ar" => self.ar = Some(string(value)?),
+			"deps" => match value.as_array().map(|deps| deps.as_slice()) {
+				Some([folder]) => self.deps = Some((string(folder)?, None)),
+				Some([folder, download_link]) => self.deps = Some((string(folder)?, Some(string(download_link)?))),
+				_ => return Err(error("expect [\"<folder>\", \"<download link>\"] or [\"<folder>\"]")),
+			},
+			"envs" => self.envs = table(value)?,
+			"sdk-envs" => {
+				self.sdk_envs = value
+					.as_table()
+					.ok_or_else(|| error("expect a table of folder arrays"))?
+					.iter()
+					.map(|(k, v)| {
+						v.as_array()
+							.and_then(|folders| folders.iter().map(|v| v.as_str().map(ToOwned::to_owned)).collect())
+							.map(|folders| (k.to_owned(), folders))
+							.ok_or_else(|| (format!("{}.{}", key, k), String::from("expect an array of folders")))
+					})
+					.collect::<Result<_, _>>()?
+			}
+			"packages" => {
+				self.packages = value
+					.as_table()
+					.ok_or_else(|| error("expect a table of package arrays"))?
+					.iter()
+					.map(|(k, v)| {
+						let key = format!("{}.{}", key, k);
+						k.parse::<PackageManager>().map_err(|reason| (key.clone(), reason))?;
+						v.as_array()
+							.and_then(|packages| packages.iter().map(|v| v.as_str().map(ToOwned::to_owned)).collect())
+							.map(|packages| (k.to_owned(), packages))
+							.ok_or_else(|| (key, String::from("expect an array of packages")))
+					})
+					.collect::<Result<_, _>>()?
+			}
+			"hints" => self.hints = table(value)?.into_iter().collect(),
+			"static" => self.static_link = value.as_bool().ok_or_else(|| error("expect a boolean"))?,
+			_ => return Ok(false),
+		}
+
+		Ok(true)
+	}
+
+	/// A TARGET without any linker can not be built
+	pub fn is_complete(&self) -> bool {
+		self.native_linker.is_some() || !self.linkers.is_empty()
+	}
+
+	/// The packages of `requires` declared for the package manager, `None` to use the built-in ones
+	pub fn packages(&self, manager: PackageManager) -> Option<&[String]> {
+		self.packages.get(&manager.to_string()).map(Vec::as_slice)
+	}
+
+	/// The linker candidates of the HOST
+	pub fn linkers(&self, host: &str) -> Vec<&str> {
+		match &self.native_linker {
+			Some(linker) if host == self.triple => vec![linker.as_str()],
+			_ => self.linkers.iter().map(String::as_str).collect(),
+		}
+	}
+
+	/// The most specific install hint of the HOST OS, with the placeholders replaced
+	pub fn hint(&self, host_os: &OS, host: &str, toolchain: &str) -> Option<String> {
+		host_os
+			.hint_keys()
+			.iter()
+			.find_map(|key| self.hints.get(key))
+			.map(|hint| {
+				hint.replace("{mingw-crt2}", &self.mingw_crt2(host_os))
+					.replace("{toolchain}", toolchain)
+					.replace("{host}", host)
+					.replace("{triple}", &self.triple)
+			})
+	}
+
+	/// The `crt2.o` workaround of the mingw TARGETs, the mingw is the prefix of the linker e.g. `x86_64-w64-mingw32`
+	fn mingw_crt2(&self, host_os: &OS) -> String {
+		// the thread model of Debian's alternatives follows `-gcc` e.g. `x86_64-w64-mingw32-gcc-posix`
+		let mingw = self
+			.linkers
+			.first()
+			.and_then(|linker| linker.split_once("-gcc"))
+			.map(|(mingw, _)| mingw)
+			.unwrap_or_default();
+		let (mingw_lib, steps) = match host_os {
+			OS::macOS => (
+				format!(
+					"$(brew --prefix mingw-w64)/toolchain-{}/{}/lib",
+					mingw.split('-').next().unwrap_or_default(),
+					mingw
+				),
+				vec![MINGW_CRT2],
+			),
+			_ => (format!("/usr/{}/lib", mingw), vec![MINGW_CRT2, MINGW_IPHLPAPI]),
+		};
+
+		let mut hint = steps.join("\n").replace("{mingw-lib}", &mingw_lib);
+		hint.push_str("\ncd -");
+
+		hint
+	}
+}
+
+/// The supported TARGETs, the built-in ones extended by the config file
+#[derive(Clone, Debug)]
+pub struct Registry {
+	targets: BTreeMap<String, TargetSpec>,
+}
+
+impl Registry {
+	pub fn builtin() -> Self {
+		let mut registry = Self {
+			targets: BTreeMap::new(),
+		};
+		let value = BUILTIN_TARGETS
+			.parse::<toml::Value>()
+			.expect("`targets.toml` is a valid TOML");
+
+		for (triple, table) in value.as_table().expect("`targets.toml` is a table") {
+			let mut spec = TargetSpec::new(triple);
+			for (k, v) in table.as_table().expect("`targets.toml` targets are tables") {
+				assert!(
+					spec.set(k, v).expect("`targets.toml` values are valid"),
+					"`targets.toml` unknown key `{}`",
+					k
+				);
+			}
+
+			registry.insert(spec);
+		}
+
+		registry
+	}
+
+	pub fn get(&self, triple: &str) -> Option<&TargetSpec> {
+		self.targets.get(triple)
+	}
+
+	pub fn contains(&self, triple: &str) -> bool {
+		self.targets.contains_key(triple)
+	}
+
+	/// Add a new TARGET or replace the existed one
+	pub fn insert(&mut self, spec: TargetSpec) {
+		self.targets.insert(spec.triple.clone(), spec);
+	}
+
+	pub fn triples(&self) -> Vec<&str> {
+		self.targets.keys().map(String::as_str).collect()
+	}
+}
+
+impl Default for Registry {
+	fn default() -> Self {
+		Self::builtin()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::host::Arch;
+
+	#[test]
+	fn builtin_targets() {
+		let registry = Registry::builtin();
+		for triple in registry.triples() {
+			let spec = registry.get(triple).unwrap();
+			assert!(spec.is_complete(), "{}", triple);
+			assert!(spec.deps.is_some(), "{}", triple);
+		}
+
+		let spec = registry.get("x86_64-unknown-linux-gnu").unwrap();
+		assert_eq!(spec.linkers("x86_64-unknown-linux-gnu"), ["gcc"]);
+		assert_eq!(
+			spec.linkers("x86_64-apple-darwin"),
+			["x86_64-unknown-linux-gnu-gcc", "x86_64-linux-gnu-gcc"]
+		);
+		assert!(!spec.static_link);
+		assert!(registry.get("x86_64-unknown-linux-musl").unwrap().static_link);
+		let hint = registry
+			.get("i686-pc-windows-gnu")
+			.unwrap()
+			.hint(&OS::macOS, "x86_64-apple-darwin", "nightly")
+			.unwrap();
+		assert_eq!(
+			hint,
+			"brew install mingw-w64
+follow https://github.com/rust-lang/rust/issues/48272#issuecomment-429596397:
+cd ~/.rustup/toolchains/nightly-x86_64-apple-darwin/lib/rustlib/i686-pc-windows-gnu/lib
+cp $(brew --prefix mingw-w64)/toolchain-i686/i686-w64-mingw32/lib/*crt2.o ./
+cd -"
+		);
+		// not released, built by hand
+		assert_eq!(
+			registry.get("aarch64-unknown-linux-gnu").unwrap().deps,
+			Some((String::from("linux-aarch64"), None))
+		);
+	}
+
+	#[test]
+	fn arm_targets() {
+		let registry = Registry::builtin();
+		let armv7 = registry.get("armv7-unknown-linux-gnueabihf").unwrap();
+		let arm = registry.get("arm-unknown-linux-gnueabi").unwrap();
+		assert_eq!(
+			armv7.linkers("x86_64-unknown-linux-gnu"),
+			["arm-linux-gnueabihf-gcc", "armv7-unknown-linux-gnueabihf-gcc"]
+		);
+		assert_eq!(arm.linkers("x86_64-unknown-linux-gnu"), ["arm-linux-gnueabi-gcc"]);
+		// the 32-bit ARM HOST is an armv7 board, it builds with its own gcc
+		let host = Arch::arm.triple(&OS::Linux(Default::default()));
+		assert_eq!(host, "armv7-unknown-linux-gnueabihf");
+		assert_eq!(armv7.linkers(&host), ["gcc"]);
+		assert_eq!(arm.linkers(&host), ["arm-linux-gnueabi-gcc"]);
+
+		for spec in [armv7, arm] {
+			assert!(!spec.static_link, "{}", spec.triple);
+			assert!(spec.envs.iter().any(|(k, _)| k == "SYSROOT"), "{}", spec.triple);
+		}
+		assert_eq!(
+			armv7.hint(&OS::macOS, "aarch64-apple-darwin", "nightly").unwrap(),
+			"brew tap messense/macos-cross-toolchains && brew install armv7-unknown-linux-gnueabihf"
+		);
+	}
+
+	#[test]
+	fn mingw_crt2_placeholders() {
+		let mut spec = TargetSpec::new("x86_64-pc-windows-gnu");
+		spec.linkers = vec![String::from("x86_64-w64-mingw32-gcc-posix")];
+		spec.hints.insert(String::from("linux"), String::from("install mingw-w64\n{mingw-crt2}"));
+
+		let hint = spec
+			.hint(
+				&OS::Linux(Default::default()),
+				"x86_64-unknown-linux-gnu",
+				"nightly-2020-10-06",
+			)
+			.unwrap();
+		assert_eq!(
+			hint,
+			"install mingw-w64
+follow https://github.com/rust-lang/rust/issues/48272#issuecomment-429596397:
+cd ~/.rustup/toolchains/nightly-2020-10-06-x86_64-unknown-linux-gnu/lib/rustlib/x86_64-pc-windows-gnu/lib
+cp /usr/x86_64-w64-mingw32/lib/*crt2.o ./
+sudo ln -s /usr/x86_64-w64-mingw32/lib/libiphlpapi.a /usr/x86_64-w64-mingw32/lib/libIphlpapi.a
+cd -"
+		);
+
+		spec.linkers = vec![String::from("x86_64-w64-mingw32-gcc")];
+		assert!(spec
+			.mingw_crt2(&OS::macOS)
+			.contains("cp $(brew --prefix mingw-w64)/toolchain-x86_64/x86_64-w64-mingw32/lib/*crt2.o ./"));
+	}
+
+	#[test]
+	fn declared_packages() {
+		let value = |s: &str| s.parse::<toml::Value>().unwrap()["packages"].clone();
+		let mut spec = TargetSpec::new("powerpc64le-unknown-linux-gnu");
+		spec.set(
+			"packages",
+			&value(r#"packages = { apt = ["gcc-powerpc64le-linux-gnu"], dnf = ["gcc-powerpc64le-linux-gnu", "glibc-devel"] }"#),
+		)
+		.unwrap();
+		assert_eq!(
+			spec.packages(PackageManager::Apt).unwrap(),
+			[String::from("gcc-powerpc64le-linux-gnu")]
+		);
+		assert_eq!(spec.packages(PackageManager::Dnf).unwrap().len(), 2);
+		assert!(spec.packages(PackageManager::Pacman).is_none());
+
+		assert_eq!(
+			spec.set("packages", &value(r#"packages = { apt-get = ["gcc"] }"#)).unwrap_err().0,
+			"packages.apt-get"
+		);
+		assert_eq!(
+			spec.set("packages", &value(r#"packages = { apt = "gcc" }"#)).unwrap_err(),
+			(String::from("packages.apt"), String::from("expect an array of packages"))
+		);
+	}
+}
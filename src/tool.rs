@@ -0,0 +1,333 @@
+// --- std ---
+use std::process::Command;
+// --- custom ---
+use crate::{
+	builder::Options,
+	error::Error,
+	util::{run, run_with_output},
+};
+
+pub(crate) const RUSTUP_UNIX: &str = "curl https://sh.rustup.rs -sSf | sh";
+pub(crate) const RUSTUP_WINDOWS: &str = "https://www.rust-lang.org/tools/install";
+pub(crate) const WASM_GC: &str = "https://github.com/alexcrichton/wasm-gc";
+
+#[derive(Debug, Default)]
+pub struct Tool {
+	pub rustup: String,
+	pub cargo: String,
+	/// The toolchain of the native build
+	pub toolchain: String,
+	/// The toolchain of the wasm runtime, might be the same as `toolchain`, empty without `options.wasm`
+	pub wasm_toolchain: String,
+	pub run_target: String,
+	pub wasm_target: String,
+	pub wasm_gc: String,
+	/// The targets and components of `rust-toolchain(.toml)` e.g. `("component rustfmt", "rustfmt")`,
+	/// the value is empty if it is not installed
+	pub pinned: Vec<(String, String)>,
+}
+
+impl Tool {
+	pub fn new(options: &Options) -> Result<Self, Error> {
+		let run_target = options.run_target();
+		let mut tool = Self {
+			rustup: String::new(),
+			cargo: String::new(),
+			toolchain: format!("{}-{}", options.toolchain, options.host),
+			wasm_toolchain: format!("{}-{}", options.wasm_toolchain(), options.host),
+			wasm_target: if options.wasm {
+				String::from("wasm32-unknown-unknown")
+			} else {
+				String::new()
+			},
+			run_target: options.run_target().to_owned(),
+			wasm_gc: String::new(),
+			pinned: vec![],
+		};
+
+		match run(Command::new("rustup").arg("--version")) {
+			Ok(version) => {
+				tool.rustup = version;
+				options.output.tool(run_target, "rustup", Ok(&tool.rustup));
+			}
+			Err(e) => {
+				if e.is_not_found() {
+					let os = if cfg!(target_os = "windows") {
+						RUSTUP_WINDOWS
+					} else {
+						RUSTUP_UNIX
+					};
+					options.output.tool(run_target, "rustup", Err(os));
+				}
+
+				return Err(e);
+			}
+		}
+
+		tool.cargo = run(Command::new("cargo").arg("--version"))?;
+		options.output.tool(run_target, "cargo", Ok(&tool.cargo));
+
+		{
+			let toolchain_list = run(Command::new("rustup").args(["toolchain", "list"]))?;
+			let toolchain = check_toolchain(options, "toolchain", &tool.toolchain, &toolchain_list)?;
+			// the wasm tools are left empty without `options.wasm`
+			tool.wasm_toolchain = if !options.wasm {
+				String::new()
+			} else if tool.wasm_toolchain == tool.toolchain {
+				toolchain.clone()
+			} else {
+				check_toolchain(options, "wasm toolchain", &tool.wasm_toolchain, &toolchain_list)?
+			};
+			tool.toolchain = toolchain;
+		}
+
+		// the run target on the native toolchain, wasm32 on the wasm one
+		for (toolchain, target) in [
+			(&tool.toolchain, &mut tool.run_target),
+			(&tool.wasm_toolchain, &mut tool.wasm_target),
+		] {
+			if !toolchain.is_empty() {
+				*target = check_target(options, toolchain, target)?;
+			}
+		}
+
+		if let (false, Some(rust_toolchain)) = (tool.toolchain.is_empty(), &options.rust_toolchain) {
+			let installed_targets =
+				run(Command::new("rustup").args(["target", "list", "--installed", "--toolchain", &tool.toolchain]))?;
+			let installed_components =
+				run(Command::new("rustup").args(["component", "list", "--installed", "--toolchain", &tool.toolchain]))?;
+			let targets = rust_toolchain
+				.targets
+				.iter()
+				.filter(|target| *target != run_target && *target != "wasm32-unknown-unknown")
+				.map(|target| ("target", target, installed_targets.lines().any(|line| line == target)));
+			// the host specific components are listed with the HOST e.g. `rustfmt-x86_64-unknown-linux-gnu`
+			let components = rust_toolchain.components.iter().map(|component| {
+				let host_component = format!("{}-{}", component, options.host);
+				(
+					"component",
+					component,
+					installed_components
+						.lines()
+						.any(|line| line == component || line == host_component),
+				)
+			});
+
+			for (kind, name, installed) in targets.chain(components) {
+				let key = format!("{} {}", kind, name);
+				if !installed {
+					options.output.tool(run_target, kind, Err(name));
+
+					if options.install {
+						let package = if kind == "target" {
+							Some(("rust-std", name.as_str()))
+						} else {
+							None
+						};
+						install(
+							options,
+							&tool.toolchain,
+							package,
+							&[kind, "add", name, "--toolchain", &tool.toolchain],
+						)?;
+					} else {
+						tool.pinned.push((key, String::new()));
+						continue;
+					}
+				}
+
+				options.output.tool(run_target, kind, Ok(name));
+				tool.pinned.push((key, name.to_owned()));
+			}
+		}
+
+		if options.wasm {
+			if let Err(e) = run(Command::new("wasm-gc").arg("--help")) {
+				if e.is_not_found() {
+					options.output.tool(run_target, "wasm-gc", Err(WASM_GC));
+
+					if !options.install {
+						return Ok(tool);
+					}
+					if options.offline {
+						return Err(Error::Unavailable(format!(
+							"wasm-gc is not installed and `--offline` forbids downloading it, install it from {} on a connected machine",
+							WASM_GC
+						)));
+					}
+
+					run_with_output(
+						Command::new("cargo").args(["install", "--git", WASM_GC]),
+						&options.output,
+					)?;
+				} else {
+					return Err(e);
+				}
+			}
+
+			tool.wasm_gc = String::from(WASM_GC);
+			options.output.tool(run_target, "wasm-gc", Ok(&tool.wasm_gc));
+		}
+
+		Ok(tool)
+	}
+}
+
+/// Whether the toolchain (with its HOST) is a line of `rustup toolchain list`, e.g. `nightly-<host> (default)`
+pub(crate) fn is_installed(toolchain_list: &str, toolchain: &str) -> bool {
+	toolchain_list
+		.lines()
+		.any(|line| line.split_whitespace().next() == Some(toolchain))
+}
+
+/// Check (and install) the toolchain, return it empty if it is not installed
+fn check_toolchain(options: &Options, name: &str, toolchain: &str, toolchain_list: &str) -> Result<String, Error> {
+	if !is_installed(toolchain_list, toolchain) {
+		options.output.tool(options.run_target(), name, Err(toolchain));
+
+		if !options.install {
+			return Ok(String::new());
+		}
+
+		install(options, toolchain, None, &["toolchain", "install", toolchain])?;
+	}
+
+	options.output.tool(options.run_target(), name, Ok(toolchain));
+
+	Ok(toolchain.to_owned())
+}
+
+/// Check (and add) the target of the toolchain, return it empty if it is not installed
+fn check_target(options: &Options, toolchain: &str, target: &str) -> Result<String, Error> {
+	let target_list = run(Command::new("rustup").args(["target", "list", "--installed", "--toolchain", toolchain]))?;
+	if !target_list.lines().any(|line| line.trim() == target) {
+		options.output.tool(options.run_target(), "target", Err(target));
+
+		if !options.install {
+			return Ok(String::new());
+		}
+
+		install(
+			options,
+			toolchain,
+			Some(("rust-std", target)),
+			&["target", "add", target, "--toolchain", toolchain],
+		)?;
+	}
+
+	options.output.tool(options.run_target(), "target", Ok(target));
+
+	Ok(target.to_owned())
+}
+
+/// Run `rustup <args>` against the mirror (if any), the mirror is checked for the `package` of the toolchain first
+///
+/// `--offline` without a mirror fails instead of downloading from the network
+fn install(options: &Options, toolchain: &str, package: Option<(&str, &str)>, args: &[&str]) -> Result<(), Error> {
+	let channel = toolchain
+		.strip_suffix(&format!("-{}", options.host))
+		.unwrap_or(toolchain);
+	let mut command = Command::new("rustup");
+	command.args(args);
+
+	match &options.mirror {
+		Some(mirror) => {
+			mirror.check(channel, package).map_err(Error::Unavailable)?;
+			mirror.apply(&mut command);
+		}
+		None if options.offline => {
+			let missing = match package {
+				Some((package, target)) => format!("`{}` for {} of `{}`", package, target, channel),
+				None => format!("`{}`", channel),
+			};
+
+			return Err(Error::Unavailable(format!(
+				"{} is not installed and `--offline` forbids downloading it, pass `--rustup-mirror` to install it from a mirror",
+				missing
+			)));
+		}
+		None => (),
+	}
+
+	run_with_output(&mut command, &options.output)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{builder::Builder, config::Config, env_var::EnvVar, error::Stage, mirror::Mirror, util::TempDir};
+
+	#[test]
+	fn report_without_install() {
+		// e.g. the CI mode
+		let mut options = Options::new(&Config::default(), Some(String::from("x86_64-unknown-linux-gnu"))).unwrap();
+		options.toolchain = String::from("nightly-1999-01-01");
+		options.wasm = false;
+		options.install = false;
+
+		let tool = Tool::new(&options).unwrap();
+		assert!(tool.toolchain.is_empty());
+		let builder = Builder { options, tool, env_var: EnvVar::default() };
+		let e = builder.check().unwrap_err();
+		assert_eq!(e.exit_code(), Stage::Toolchain.exit_code());
+		assert!(e.to_string().contains("toolchain"), "{}", e);
+	}
+
+	#[test]
+	fn installed_toolchains() {
+		let toolchain_list = "stable-x86_64-unknown-linux-gnu (default)
+nightly-2020-10-06-x86_64-unknown-linux-gnu
+nightly-x86_64-unknown-linux-gnu (override)
+1.82.0-aarch64-unknown-linux-gnu
+";
+		for toolchain in [
+			"stable-x86_64-unknown-linux-gnu",
+			"nightly-2020-10-06-x86_64-unknown-linux-gnu",
+			"nightly-x86_64-unknown-linux-gnu",
+		] {
+			assert!(is_installed(toolchain_list, toolchain), "{}", toolchain);
+		}
+		// a prefix or a part of an installed one
+		assert!(!is_installed(toolchain_list, "nightly-2020-10-0-x86_64-unknown-linux-gnu"));
+		assert!(!is_installed(toolchain_list, "stable"));
+		assert!(!is_installed(toolchain_list, "2020-10-06-x86_64-unknown-linux-gnu"));
+		// another HOST
+		assert!(!is_installed(toolchain_list, "1.82.0-x86_64-unknown-linux-gnu"));
+		assert!(!is_installed(toolchain_list, "stable-x86_64-unknown-linux-musl"));
+		assert!(!is_installed("", "stable-x86_64-unknown-linux-gnu"));
+	}
+
+	#[test]
+	fn offline_install() {
+		let dir = TempDir::new("offline-install");
+		let mut options = Options::new(&Config::default(), Some(String::from("x86_64-unknown-linux-gnu"))).unwrap();
+		options.offline = true;
+		let toolchain = format!("nightly-2020-10-06-{}", options.host);
+		let args = ["toolchain", "install", toolchain.as_str()];
+
+		// the mirror without the manifest of the channel
+		options.mirror = Some(Mirror::new(&dir.to_string_lossy(), &dir));
+		match install(&options, &toolchain, None, &args).unwrap_err() {
+			Error::Unavailable(reason) => {
+				assert!(reason.contains("`nightly-2020-10-06` is not in the mirror"), "{}", reason);
+			}
+			e => panic!("{:?}", e),
+		}
+		let package = Some(("rust-std", "wasm32-unknown-unknown"));
+		match install(&options, &toolchain, package, &args).unwrap_err() {
+			Error::Unavailable(reason) => {
+				assert!(reason.contains("nightly-2020-10-06"), "{}", reason);
+			}
+			e => panic!("{:?}", e),
+		}
+
+		// no mirror to install from
+		options.mirror = None;
+		match install(&options, &toolchain, None, &args).unwrap_err() {
+			Error::Unavailable(reason) => {
+				assert!(reason.contains("`--offline` forbids downloading"), "{}", reason);
+			}
+			e => panic!("{:?}", e),
+		}
+	}
+}
@@ -0,0 +1,395 @@
+// --- std ---
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+	process::Command,
+};
+// --- external ---
+use colored::Colorize;
+// --- custom ---
+use crate::{
+	builder::{self, pack_binary, package_name, Builder, Options, Task},
+	error::{Error, Stage, WithPath},
+	message::{Event, Output},
+	package::install_missing,
+	util::run,
+};
+
+/// The name of the universal binary's folders and archive
+pub const UNIVERSAL_APPLE_DARWIN: &str = "universal-apple-darwin";
+/// The TARGETs which are merged into the universal binary
+pub const APPLE_TARGETS: &[&str] = &["x86_64-apple-darwin", "aarch64-apple-darwin"];
+
+/// Build each of the `APPLE_TARGETS`, then merge them into a universal macOS binary with `lipo`
+#[derive(Debug)]
+pub struct Universal {
+	pub builders: Vec<Builder>,
+}
+
+impl Universal {
+	/// Run the task for the `APPLE_TARGETS` of `options`, building and packing are on the universal binary and the
+	/// wasm is built once, the other tasks run for each TARGET under its `TARGET:` line
+	pub fn run(mut options: Vec<Options>, task: Task) -> Result<(), Error> {
+		match task {
+			Task::Clean { deps } => {
+				for options in &options {
+					builder::clean(options, deps)?;
+				}
+
+				clean(&options[0].root, &options[0].output)
+			}
+			Task::Pack => {
+				for options in &mut options {
+					options.install = false;
+				}
+
+				Universal::new(options)?.pack()
+			}
+			Task::All | Task::Build => {
+				let mut proceed = true;
+				for options in &options {
+					proceed &= install_missing(options)?;
+				}
+				if !proceed {
+					return Ok(());
+				}
+
+				let pack = options.iter().any(|options| options.pack);
+				let universal = Universal::new(options)?;
+				universal.check()?;
+				universal.build()?;
+				if task == Task::All && pack {
+					universal.pack()?;
+				}
+
+				Ok(())
+			}
+			Task::Wasm => {
+				let mut proceed = true;
+				for options in &mut options {
+					options.wasm = true;
+					proceed &= install_missing(options)?;
+				}
+				if !proceed {
+					return Ok(());
+				}
+
+				let universal = Universal::new(options)?;
+				universal.check()?;
+				universal.build_wasm()
+			}
+			_ => options.into_iter().try_for_each(|options| {
+				let target = options.target.as_deref().unwrap_or_default();
+				options.output.line(&format!("{} {}", "TARGET:".green(), target.cyan()));
+
+				Builder::run(options, task)
+			}),
+		}
+	}
+
+	/// `options` of each of the `APPLE_TARGETS`
+	pub fn new(options: Vec<Options>) -> Result<Self, Error> {
+		Ok(Self {
+			builders: options.into_iter().map(Builder::new).collect::<Result<_, _>>()?,
+		})
+	}
+
+	pub fn check(&self) -> Result<(), Error> {
+		for builder in &self.builders {
+			builder.check()?;
+		}
+
+		Ok(())
+	}
+
+	/// The wasm is the same for every TARGET, build it once with the first builder (with `options.wasm`)
+	fn wasm_builder(&self) -> Option<&Builder> {
+		self.builders.iter().find(|builder| builder.options.wasm)
+	}
+
+	/// Build the wasm once, with the shared wasm toolchain
+	pub fn build_wasm(&self) -> Result<(), Error> {
+		match self.wasm_builder() {
+			Some(builder) => builder.build_wasm(),
+			None => Ok(()),
+		}
+	}
+
+	/// Build the wasm once (with `options.wasm`), then each TARGET and merge them
+	pub fn build(&self) -> Result<(), Error> {
+		self.build_wasm()?;
+		for builder in &self.builders {
+			builder.build_native()?;
+		}
+
+		self.stage(Stage::Build, || {
+			let package_name = package_name(self.root())?;
+			let binaries = self
+				.builders
+				.iter()
+				.map(|builder| builder.binary_path(&package_name))
+				.collect::<Vec<_>>();
+			let universal_path = self.binary_path(&package_name);
+			if let Some(dir) = universal_path.parent() {
+				fs::create_dir_all(dir).with_path(dir)?;
+			}
+
+			self.lipo(&binaries, &universal_path)?;
+			self.output().found("universal", &universal_path.to_string_lossy());
+			self.output().emit(Event::Artifact {
+				target: UNIVERSAL_APPLE_DARWIN,
+				path: &universal_path,
+			});
+
+			Ok(())
+		})
+	}
+
+	/// Pack the universal binary with the merged ld-library into `target/universal-apple-darwin-<project-name>.tar.gz`
+	pub fn pack(&self) -> Result<(), Error> {
+		self.stage(Stage::Pack, || {
+			let root = self.root();
+			let package_name = package_name(root)?;
+			let universal_path = self.binary_path(&package_name);
+			if !universal_path.is_file() {
+				return Err(io::Error::new(io::ErrorKind::NotFound, "not found, build it first"))
+					.with_path(&universal_path);
+			}
+
+			let ld_library_dir = root.join("target").join(UNIVERSAL_APPLE_DARWIN).join("ld-library");
+			if ld_library_dir.is_dir() {
+				fs::remove_dir_all(&ld_library_dir).with_path(&ld_library_dir)?;
+			}
+			fs::create_dir_all(&ld_library_dir).with_path(&ld_library_dir)?;
+			self.merge_ld_library(&ld_library_dir)?;
+
+			pack_binary(
+				self.output(),
+				root,
+				UNIVERSAL_APPLE_DARWIN,
+				&package_name,
+				&universal_path,
+				Some(&ld_library_dir),
+			)
+		})
+	}
+
+	fn root(&self) -> &Path {
+		&self.builders[0].options.root
+	}
+
+	fn output(&self) -> &Output {
+		&self.builders[0].options.output
+	}
+
+	fn stage<T, F: FnOnce() -> Result<T, Error>>(&self, stage: Stage, f: F) -> Result<T, Error> {
+		f().map_err(|e| e.in_stage(stage, UNIVERSAL_APPLE_DARWIN))
+	}
+
+	fn binary_path(&self, package_name: &str) -> PathBuf {
+		let mut path = self.root().join("target");
+		path.push(UNIVERSAL_APPLE_DARWIN);
+		if self.builders[0].options.debug {
+			path.push("debug");
+		} else {
+			path.push("release");
+		}
+		path.push(package_name);
+
+		path
+	}
+
+	/// osxcross prefixes its `lipo` like the linker e.g. `x86_64-apple-darwin19-lipo`, macOS has a plain one
+	fn lipo(&self, inputs: &[PathBuf], output: &Path) -> Result<(), Error> {
+		let mut last_error = None;
+		for lipo in &lipos(self.builders.iter().map(|builder| builder.env_var.target_cc.as_str())) {
+			match run(Command::new(lipo)
+				.arg("-create")
+				.args(inputs)
+				.arg("-output")
+				.arg(output))
+			{
+				Ok(_) => return Ok(()),
+				Err(e) if e.is_not_found() => last_error = Some(e),
+				Err(e) => return Err(e),
+			}
+		}
+
+		Err(last_error.unwrap_or(Error::Missing(vec![String::from("lipo")])))
+	}
+
+	/// Merge the same name libraries of each TARGET with `lipo`, copy the others
+	fn merge_ld_library(&self, output: &Path) -> Result<(), Error> {
+		let mut ld_library_dirs = vec![];
+		for builder in &self.builders {
+			if builder.env_var.deps.is_empty() {
+				return Err(
+					Error::Missing(vec![String::from("deps")]).in_stage(Stage::Pack, builder.options.run_target())
+				);
+			}
+
+			ld_library_dirs.push(builder.options.root.join(&builder.env_var.deps).join("ld-library"));
+		}
+
+		for (name, inputs) in group_libraries(&ld_library_dirs, output)? {
+			if inputs.len() == 1 {
+				fs::copy(&inputs[0], &name).with_path(&inputs[0])?;
+			} else {
+				self.lipo(&inputs, &name)?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// The `lipo`s to try, the ones of osxcross after the prefix of the `target_cc`s e.g. `x86_64-apple-darwin19-clang`,
+/// the plain one last
+fn lipos<'a>(target_ccs: impl Iterator<Item = &'a str>) -> Vec<String> {
+	let mut lipos = target_ccs
+		.filter_map(|target_cc| target_cc.strip_suffix("-clang"))
+		.map(|prefix| format!("{}-lipo", prefix))
+		.collect::<Vec<_>>();
+	lipos.push(String::from("lipo"));
+
+	lipos
+}
+
+/// The libraries of the `ld-library` dirs by their path in `output`, with the same name ones of each dir
+fn group_libraries(ld_library_dirs: &[PathBuf], output: &Path) -> Result<Vec<(PathBuf, Vec<PathBuf>)>, Error> {
+	let mut libraries = Vec::<(PathBuf, Vec<PathBuf>)>::new();
+	for ld_library_dir in ld_library_dirs {
+		let mut entries = fs::read_dir(ld_library_dir)
+			.with_path(ld_library_dir)?
+			.map(|entry| entry.map(|entry| entry.path()))
+			.collect::<Result<Vec<_>, _>>()
+			.with_path(ld_library_dir)?;
+		// the same order on every file system
+		entries.sort();
+
+		for path in entries {
+			if !path.is_file() {
+				continue;
+			}
+
+			let name = output.join(path.file_name().unwrap_or_default());
+			if let Some((_, inputs)) = libraries.iter_mut().find(|(name_, _)| *name_ == name) {
+				inputs.push(path);
+			} else {
+				libraries.push((name, vec![path]));
+			}
+		}
+	}
+
+	Ok(libraries)
+}
+
+/// Remove the universal binary and its packed artifacts
+pub fn clean(root: &Path, output: &Output) -> Result<(), Error> {
+	let package_name = package_name(root).map_err(|e| e.in_stage(Stage::Pack, UNIVERSAL_APPLE_DARWIN))?;
+	for path in [
+		root.join("target").join(UNIVERSAL_APPLE_DARWIN),
+		root.join("target")
+			.join(format!("{}-{}", UNIVERSAL_APPLE_DARWIN, package_name)),
+		root.join("target")
+			.join(format!("{}-{}.tar.gz", UNIVERSAL_APPLE_DARWIN, package_name)),
+	]
+	.iter()
+	{
+		if path.is_dir() {
+			fs::remove_dir_all(path).with_path(path)?;
+		} else if path.is_file() {
+			fs::remove_file(path).with_path(path)?;
+		} else {
+			continue;
+		}
+
+		output.found("removed", &path.to_string_lossy());
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{config::Config, env_var::EnvVar, tool::Tool, util::TempDir};
+
+	#[test]
+	fn wasm_once() {
+		let builder = |target: &str, wasm: bool| {
+			let mut options = Options::new(&Config::default(), Some(target.to_owned())).unwrap();
+			options.wasm = wasm;
+
+			Builder {
+				options,
+				tool: Tool::default(),
+				env_var: EnvVar::default(),
+			}
+		};
+		let target = |universal: &Universal| {
+			universal
+				.wasm_builder()
+				.and_then(|builder| builder.options.target.clone())
+		};
+
+		let universal = Universal {
+			builders: APPLE_TARGETS.iter().map(|target| builder(target, true)).collect(),
+		};
+		assert_eq!(target(&universal).as_deref(), Some(APPLE_TARGETS[0]));
+		let universal = Universal {
+			builders: vec![builder(APPLE_TARGETS[0], false), builder(APPLE_TARGETS[1], true)],
+		};
+		assert_eq!(target(&universal).as_deref(), Some(APPLE_TARGETS[1]));
+		// `--no-wasm`, nothing to build
+		let universal = Universal {
+			builders: APPLE_TARGETS.iter().map(|target| builder(target, false)).collect(),
+		};
+		assert!(target(&universal).is_none());
+		assert!(universal.build_wasm().is_ok());
+	}
+
+	#[test]
+	fn lipo_names() {
+		assert_eq!(
+			lipos(["x86_64-apple-darwin19-clang", "aarch64-apple-darwin20.4-clang"].iter().copied()),
+			["x86_64-apple-darwin19-lipo", "aarch64-apple-darwin20.4-lipo", "lipo"]
+		);
+		// the plain `clang` of macOS
+		assert_eq!(lipos(["clang", "clang"].iter().copied()), ["lipo"]);
+		assert_eq!(lipos(["o64-clang", "gcc"].iter().copied()), ["o64-lipo", "lipo"]);
+	}
+
+	#[test]
+	fn group_same_name_libraries() {
+		let dir = TempDir::new("group-libraries");
+		let x86_64 = dir.join("x86_64-apple-darwin").join("ld-library");
+		let aarch64 = dir.join("aarch64-apple-darwin").join("ld-library");
+		for (ld_library_dir, libraries) in [
+			(&x86_64, &["librocksdb.dylib", "libssl.dylib"][..]),
+			(&aarch64, &["libssl.dylib", "libz.dylib"][..]),
+		] {
+			fs::create_dir_all(ld_library_dir.join("pkgconfig")).unwrap();
+			for library in libraries {
+				fs::write(ld_library_dir.join(library), "").unwrap();
+			}
+		}
+		let output = dir.join(UNIVERSAL_APPLE_DARWIN).join("ld-library");
+
+		assert_eq!(
+			group_libraries(&[x86_64.clone(), aarch64.clone()], &output).unwrap(),
+			vec![
+				(
+					output.join("librocksdb.dylib"),
+					vec![x86_64.join("librocksdb.dylib")]
+				),
+				(
+					output.join("libssl.dylib"),
+					vec![x86_64.join("libssl.dylib"), aarch64.join("libssl.dylib")]
+				),
+				(output.join("libz.dylib"), vec![aarch64.join("libz.dylib")]),
+			]
+		);
+		assert!(group_libraries(&[dir.join("missing")], &output).is_err());
+	}
+}
@@ -0,0 +1,327 @@
+// --- std ---
+use std::{
+	env,
+	fs::{self, File},
+	io::{self, BufRead, BufReader, IsTerminal, Read},
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+};
+// --- external ---
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::{header::CONTENT_LENGTH, ClientBuilder, Url};
+// --- custom ---
+use crate::{
+	error::{Error, WithPath},
+	message::{Event, Output},
+};
+
+/// Report the download progress every 1 MiB if the size is unknown
+const DOWNLOAD_STEP: u64 = 1024 * 1024;
+/// Keep at most this many lines of the errors in `Error::Command`
+const ERROR_EXCERPT_LINES: usize = 40;
+
+/// Resolve the path relative to `dir`, `~` is the home folder
+pub fn resolve_path(path: &str, dir: &Path) -> PathBuf {
+	match path.strip_prefix('~') {
+		Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => dirs::home_dir()
+			.unwrap_or_default()
+			.join(rest.trim_start_matches(['/', '\\'])),
+		_ => dir.join(path),
+	}
+}
+
+pub fn run(command: &mut Command) -> Result<String, Error> {
+	let output = command.output().map_err(|e| Error::spawn(command, e))?;
+	if output.status.success() {
+		Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+	} else {
+		Err(Error::command(command, output.status, &output.stderr))
+	}
+}
+
+/// Run with the inherited stderr, so cargo and rustup show their progress bars and colours
+pub fn run_with_output(command: &mut Command, output: &Output) -> Result<(), Error> {
+	if output.ci {
+		command.env("CARGO_TERM_COLOR", "never");
+	}
+
+	let status = command
+		.stdout(Stdio::null())
+		.status()
+		.map_err(|e| Error::spawn(command, e))?;
+	if status.success() {
+		Ok(())
+	} else {
+		Err(Error::command(command, status, &[]))
+	}
+}
+
+/// Run with the stderr shown, keep its `error` blocks (e.g. the rustc errors) in the `Error::Command`
+///
+/// The stderr is piped to read them, so cargo shows no progress bar
+pub fn run_with_excerpt(command: &mut Command, output: &Output) -> Result<(), Error> {
+	// cargo only colours a terminal, `--ci` turns the colours off
+	if output.ci {
+		command.env("CARGO_TERM_COLOR", "never");
+	} else if io::stderr().is_terminal() && env::var_os("CARGO_TERM_COLOR").is_none() {
+		command.env("CARGO_TERM_COLOR", "always");
+	}
+
+	let mut child = command
+		.stdout(Stdio::null())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| Error::spawn(command, e))?;
+	let excerpt = match child.stderr.take() {
+		Some(stderr) => error_excerpt(
+			BufReader::new(stderr)
+				.lines()
+				.map_while(Result::ok)
+				.inspect(|line| eprintln!("{}", line)),
+		),
+		None => String::new(),
+	};
+
+	let status = child.wait().map_err(|e| Error::spawn(command, e))?;
+	if status.success() {
+		Ok(())
+	} else {
+		Err(Error::command(command, status, excerpt.as_bytes()))
+	}
+}
+
+/// Run with the inherited stdio, e.g. the package manager might ask for the password
+///
+/// Its stdout goes to stderr with `--message-format=json`, stdout is for the events only
+pub fn run_interactive(command: &mut Command, output: &Output) -> Result<(), Error> {
+	if output.is_json() {
+		command.stdout(io::stderr());
+	}
+
+	let status = command.status().map_err(|e| Error::spawn(command, e))?;
+	if status.success() {
+		Ok(())
+	} else {
+		Err(Error::command(command, status, &[]))
+	}
+}
+
+struct DownloadProgress<'a, R> {
+	inner: R,
+	output: &'a Output,
+	progress_bar: ProgressBar,
+	url: &'a str,
+	downloaded: u64,
+	total: u64,
+	reported: u64,
+}
+
+impl<R: Read> Read for DownloadProgress<'_, R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.inner.read(buf).inspect(|&n| {
+			self.progress_bar.inc(n as u64);
+			self.downloaded += n as u64;
+
+			// every 10% or `DOWNLOAD_STEP`, and at the end
+			let step = if self.total == 0 {
+				DOWNLOAD_STEP
+			} else {
+				(self.total / 10).max(1)
+			};
+			if n == 0 || self.downloaded - self.reported >= step {
+				self.reported = self.downloaded;
+				match (self.downloaded * 100).checked_div(self.total) {
+					Some(percent) => self.output.progress(&format!(
+						"downloading {}: {}% ({}/{} bytes)",
+						self.url, percent, self.downloaded, self.total
+					)),
+					None => self.output.progress(&format!("downloading {}: {} bytes", self.url, self.downloaded)),
+				}
+				self.output.emit(Event::Download {
+					url: self.url,
+					downloaded: self.downloaded,
+					total: self.total,
+				});
+			}
+		})
+	}
+}
+
+/// Download the file into `dir`, return the path of it
+pub fn download(url: &str, dir: &Path, output: &Output) -> Result<PathBuf, Error> {
+	let download_error = |reason: String| Error::Download {
+		url: url.to_owned(),
+		reason,
+	};
+	let file = Url::parse(url)
+		.ok()
+		.and_then(|url| {
+			url.path_segments()
+				.and_then(|mut segments| segments.next_back().map(|file| dir.join(file)))
+		})
+		.ok_or_else(|| download_error(String::from("invalid url")))?;
+	let client = ClientBuilder::new()
+		.danger_accept_invalid_certs(true)
+		.danger_accept_invalid_hostnames(true)
+		.gzip(true)
+		.use_sys_proxy()
+		.build()
+		.map_err(|e| download_error(e.to_string()))?;
+	let resp = client
+		.get(url)
+		.send()
+		.and_then(|resp| resp.error_for_status())
+		.map_err(|e| download_error(e.to_string()))?;
+	let total_size = resp
+		.headers()
+		.get(CONTENT_LENGTH)
+		.and_then(|len| len.to_str().ok())
+		.and_then(|len| len.parse().ok())
+		.unwrap_or(0);
+
+	let pb = if output.is_json() || output.ci {
+		ProgressBar::hidden()
+	} else {
+		ProgressBar::new(total_size)
+	};
+	pb.set_style(
+		ProgressStyle::default_bar()
+			.template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+			.progress_chars("=> "),
+	);
+
+	if file.exists() {
+		fs::remove_file(&file).with_path(&file)?;
+	}
+
+	let mut source = DownloadProgress {
+		progress_bar: pb,
+		inner: resp,
+		output,
+		url,
+		downloaded: 0,
+		total: total_size,
+		reported: 0,
+	};
+	output.emit(Event::Download {
+		url,
+		downloaded: 0,
+		total: total_size,
+	});
+	let mut dest = File::create(&file).with_path(&file)?;
+	io::copy(&mut source, &mut dest)
+		.and_then(|_| dest.sync_all())
+		.with_path(&file)?;
+
+	Ok(file)
+}
+
+/// The `error` blocks of the lines without the colours, at most `ERROR_EXCERPT_LINES` lines of them
+///
+/// A block starts with a line starting with `error` and ends with an empty line, all the lines are consumed
+fn error_excerpt<I: Iterator<Item = String>>(lines: I) -> String {
+	let mut excerpt = vec![];
+	let mut in_error = false;
+	for line in lines {
+		let line = strip_ansi(&line);
+		if line.starts_with("error") {
+			in_error = true;
+		} else if line.trim().is_empty() {
+			in_error = false;
+		}
+		if in_error && excerpt.len() < ERROR_EXCERPT_LINES {
+			excerpt.push(line);
+		}
+	}
+
+	excerpt.join("\n")
+}
+
+/// Remove the colours e.g. `\x1b[1m\x1b[31merror\x1b[0m` to `error`
+fn strip_ansi(line: &str) -> String {
+	let mut stripped = String::with_capacity(line.len());
+	let mut chars = line.chars();
+	while let Some(c) = chars.next() {
+		if c == '\x1b' {
+			// skip the CSI sequence up to its final letter
+			for c in chars.by_ref() {
+				if c.is_ascii_alphabetic() {
+					break;
+				}
+			}
+		} else {
+			stripped.push(c);
+		}
+	}
+
+	stripped
+}
+
+/// The temporary dir of a test, emptied when created and removed on drop
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+	pub(crate) fn new(test: &str) -> Self {
+		let dir = env::temp_dir().join(format!("darwinia-builder-{}-{}", test, std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+
+		Self(dir)
+	}
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+	type Target = Path;
+
+	fn deref(&self) -> &Path {
+		&self.0
+	}
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TempDir {
+	fn as_ref(&self) -> &Path {
+		&self.0
+	}
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	// --- std ---
+	use std::iter;
+	// --- custom ---
+	use super::*;
+
+	#[test]
+	fn rustc_error_excerpt() {
+		let stderr = [
+			"   Compiling node-runtime v0.1.0",
+			"warning: unused import: `Foo`",
+			"",
+			"\x1b[0m\x1b[1m\x1b[38;5;9merror[E0425]\x1b[0m\x1b[0m\x1b[1m: cannot find value `x` in this scope\x1b[0m",
+			"  --> src/lib.rs:3:5",
+			"",
+			"   Compiling node v0.1.0",
+			"error: aborting due to previous error",
+		];
+		assert_eq!(
+			error_excerpt(stderr.iter().map(|line| (*line).to_owned())),
+			"error[E0425]: cannot find value `x` in this scope\n  --> src/lib.rs:3:5\n\
+			 error: aborting due to previous error"
+		);
+
+		let stderr = iter::repeat_n(String::from("error: the same"), ERROR_EXCERPT_LINES * 2);
+		assert_eq!(error_excerpt(stderr).lines().count(), ERROR_EXCERPT_LINES);
+		assert_eq!(error_excerpt(iter::once(String::from("   Finished release"))), "");
+	}
+}